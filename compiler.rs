@@ -0,0 +1,456 @@
+use std::sync::Arc;
+use crate::utils::node::{ NodeClass, Node };
+use crate::utils::error::{ ErrorClass, Err };
+use crate::utils::builtin_funcs::LIBRARY;
+
+
+pub type Site = (NodeClass, [usize; 2]); // node an instruction was compiled from, used for traces
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+	Constant(usize), // push constants[i]
+	Load(usize), // push the value bound to names[i]
+	LoadLocal(usize, usize), // name, slot resolved by the parser
+	LoadScope, // push the current namespace as an <ident> instance
+	Store(usize), // bind the top of the stack to names[i], the value stays on the stack
+	StoreLocal(usize, usize),
+	Bind(usize), // Store for a statement whose value isn't used, the value is moved off the stack instead of copied
+	BindLocal(usize, usize),
+	Pop,
+	Binary(usize), // operators[i]
+	MakeList(usize), // element count
+	Paren(usize), // element count, only the first one is kept
+	EnterScope,
+	ExitScope,
+	Jump(usize),
+	JumpIfFalse(usize),
+	Call(usize, usize), // arg count, label constant
+	TailCall(usize, usize), // out(f(...)) in a function body, replaces the current call instead of nesting
+	Builtin(usize, usize), // index in LIBRARY, arg count
+	Access(usize, usize, bool), // rhs constant, rhs chunk, rhs is a field
+	Member(usize), // replace the instance on the stack with its binding for names[i]
+	Import(usize), // import statement constant, the module is left on the stack
+	Export(usize), // constant with the names a module makes public
+
+	// control flow signals are caught by the innermost block that accepts them
+	SetupLoop(usize), // exit address
+	SetupIter(usize), // exit address
+	IterNext(usize, usize), // index name, exit address
+	Arm, // loop body starts, stop() and reset() are caught from here on
+	Collect, // loop body ends, its value is added to the comprehension
+	EndLoop,
+	SetupExpect(usize, usize), // handler address, error kind constant
+	SetupTry(usize, usize), // jump table address, constant with the kinds each catch clause accepts
+	SetupFinally(usize), // cleanup address, reached by errors and signals as well as the normal path
+	EnterFinally,
+	EndFinally, // drop the cleanup's value, re-raise the error that interrupted the try if there was one
+	PopBlock,
+}
+
+
+// object bodies are compiled once and shared through a Code node, so function values can be
+// copied around and called without recompiling them, and the chunk is freed with the last copy
+#[derive(Debug, Default)]
+pub struct Chunk {
+	pub code: Vec<Op>,
+	pub sites: Vec<Site>,
+	pub constants: Vec<Node>,
+	pub names: Vec<Node>,
+	pub ids: Vec<usize>, // interned names
+	pub operators: Vec<String>,
+	pub children: Vec<Arc<Chunk>>,
+}
+
+// chunks are compared by identity, two literals with the same body are still different functions
+impl PartialEq for Chunk {
+	fn eq (&self, other: &Chunk) -> bool { std::ptr::eq(self, other) }
+}
+
+
+struct Compiler {
+	chunk: Chunk,
+	body: bool, // compiling a function body, where out() returns from the call
+	guarded: usize // expect blocks around the current expression, they could catch the out() signal
+}
+
+impl Compiler {
+	fn new (body: bool) -> Compiler { Compiler { chunk: Chunk::default(), body, guarded: 0 } }
+
+	fn emit (&mut self, op: Op, site: &Site) -> usize {
+		self.chunk.code.push(op);
+		self.chunk.sites.push(site.clone());
+		self.chunk.code.len() - 1
+	}
+
+	// point a forward jump at the next instruction
+	fn patch (&mut self, at: usize) {
+		let target = self.chunk.code.len();
+		self.chunk.code[at] = match self.chunk.code[at] {
+			Op::Jump(_) => Op::Jump(target),
+			Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+			Op::SetupLoop(_) => Op::SetupLoop(target),
+			Op::SetupIter(_) => Op::SetupIter(target),
+			Op::IterNext(name, _) => Op::IterNext(name, target),
+			Op::SetupExpect(_, kind) => Op::SetupExpect(target, kind),
+			Op::SetupTry(_, kinds) => Op::SetupTry(target, kinds),
+			Op::SetupFinally(_) => Op::SetupFinally(target),
+			other => other
+		};
+	}
+
+	fn constant (&mut self, node: Node) -> usize {
+		self.chunk.constants.push(node);
+		self.chunk.constants.len() - 1
+	}
+
+	fn name (&mut self, key: &Node) -> usize {
+		let id = key.key_id();
+		if let Some(index) = self.chunk.ids.iter().position(|name| *name == id) { return index; }
+		let key = match *key.signature {
+			NodeClass::Local(ref name, _) => NodeClass::Symbol(name.to_string()),
+			ref other => other.clone()
+		};
+		self.chunk.names.push(Node::new(key, [0, 0], Vec::new()));
+		self.chunk.ids.push(id);
+		self.chunk.names.len() - 1
+	}
+
+	fn operator (&mut self, oper: &str) -> usize {
+		if let Some(index) = self.chunk.operators.iter().position(|op| op == oper) { return index; }
+		self.chunk.operators.push(oper.to_string());
+		self.chunk.operators.len() - 1
+	}
+
+	fn child (&mut self, chunk: Chunk) -> usize {
+		self.chunk.children.push(Arc::new(chunk));
+		self.chunk.children.len() - 1
+	}
+
+	fn expression<'a> (&mut self, tree: &Node) -> Result<(), Err<'a>> {
+		let site: Site = ((*tree.signature).clone(), tree.span);
+		match tree.id() {
+			("<-", "OperatorExpression") => self.assignment(tree, true, &site)?,
+			(":", "OperatorExpression") => self.object_expr(tree, &site)?,
+			(".", "OperatorExpression") => {
+				self.expression(&tree.branches[0])?;
+				let name = self.name(&tree.branches[1]);
+				self.emit(Op::Member(name), &site);
+			},
+			(oper, "OperatorExpression") => {
+				self.expression(&tree.branches[0])?;
+				self.expression(&tree.branches[1])?;
+				let op = self.operator(oper);
+				self.emit(Op::Binary(op), &site);
+			},
+			("_", "Field") => {
+				self.emit(Op::EnterScope, &site);
+				self.statements(tree)?;
+				self.emit(Op::ExitScope, &site);
+			},
+			("_", "List") => {
+				let len = self.collection(&tree.branches[0])?;
+				self.emit(Op::MakeList(len), &site);
+			},
+			("_", "Parenthesis") => {
+				let len = self.collection(&tree.branches[0])?;
+				self.emit(Op::Paren(len), &site);
+			},
+			("_", "Call") => self.call(&tree.branches[0], &tree.branches[1], &site)?,
+			("_", "IfExpression") => self.if_expr(tree, &site)?,
+			("_", "TryExpression") => self.try_expr(tree, &site)?,
+			("_", "ExportStatement") => {
+				for name in tree.branches[0].branches.iter() { name.validate_type("Symbol")?; }
+				let index = self.constant(tree.branches[0].clone());
+				self.emit(Op::Export(index), &site);
+			},
+			("_", "ImportStatement") => {
+				for name in tree.branches[2].branches.iter() { name.validate_type("Symbol")?; }
+				let index = self.constant(tree.clone());
+				self.emit(Op::Import(index), &site);
+			},
+			("cond", "LoopExpression") => {
+				let setup = self.emit(Op::SetupLoop(0), &site);
+				self.expression(&tree.branches[0].branches[0])?;
+				let exit = self.emit(Op::JumpIfFalse(0), &site);
+				self.loop_body(&tree.branches[1], setup + 1, &site)?;
+				self.patch(exit);
+				self.patch(setup);
+				self.emit(Op::EndLoop, &site);
+			},
+			("iter", "LoopExpression") => {
+				let index = self.name(&tree.branches[0].branches[0]); // name taken by each index
+				self.expression(&tree.branches[0].branches[1])?;
+				let setup = self.emit(Op::SetupIter(0), &site);
+				let next = self.emit(Op::IterNext(index, 0), &site);
+				// additional iterator/ index pairs become a nested loop
+				let contents = match tree.branches.len() {
+					2 => tree.branches.last().unwrap().clone(),
+					_ => Node::new(NodeClass::Field, tree.span, vec![Node::new(NodeClass::Loop("iter".to_string()), tree.span, tree.branches[1..].to_vec())])
+				};
+				self.loop_body(&contents, next, &site)?;
+				self.patch(next);
+				self.patch(setup);
+				self.emit(Op::EndLoop, &site);
+			},
+			("_", "Combinator") => {
+				return Err(Err::new(ErrorClass::CustomError(format!("Cannot invoke '{}' combinator, no target specified", tree.branches[0].into_string()?)), tree.clone()));
+			},
+			("[!]", "Symbol") => { self.emit(Op::LoadScope, &site); },
+			(_, "Symbol") => {
+				let name = self.name(tree);
+				match *tree.signature {
+					NodeClass::Local(_, slot) => self.emit(Op::LoadLocal(name, slot), &site),
+					_ => self.emit(Op::Load(name), &site)
+				};
+			},
+			("_", "Object") => {
+				let object = self.object(tree)?;
+				let index = self.constant(object);
+				self.emit(Op::Constant(index), &site);
+			},
+			_ => {
+				let index = self.constant(tree.clone());
+				self.emit(Op::Constant(index), &site);
+			}
+		}
+		Ok(())
+	}
+
+	// the assigned value stays on the stack only if it's used
+	fn assignment<'a> (&mut self, tree: &Node, keep: bool, site: &Site) -> Result<(), Err<'a>> {
+		let target = tree.branches[0].validate_type("Symbol")?;
+		self.expression(&tree.branches[1])?;
+		let name = self.name(&target);
+		match (&*target.signature, keep) {
+			(NodeClass::Local(_, slot), true) => self.emit(Op::StoreLocal(name, *slot), site),
+			(NodeClass::Local(_, slot), false) => self.emit(Op::BindLocal(name, *slot), site),
+			(_, true) => self.emit(Op::Store(name), site),
+			(_, false) => self.emit(Op::Bind(name), site)
+		};
+		Ok(())
+	}
+
+	// statements of a field, the value of the last one is left on the stack
+	fn statements<'a> (&mut self, tree: &Node) -> Result<(), Err<'a>> {
+		if tree.branches.is_empty() {
+			let index = self.constant(Node::void());
+			self.emit(Op::Constant(index), &((*tree.signature).clone(), tree.span));
+		}
+		for (count, branch) in tree.branches.iter().enumerate() {
+			let site: Site = ((*branch.signature).clone(), branch.span);
+			match (count + 1 == tree.branches.len(), branch.id()) {
+				(false, ("<-", "OperatorExpression")) => self.assignment(branch, false, &site)?,
+				(false, _) => {
+					self.expression(branch)?;
+					self.emit(Op::Pop, &site);
+				},
+				(true, _) => self.expression(branch)?
+		}}
+		Ok(())
+	}
+
+	fn collection<'a> (&mut self, tree: &Node) -> Result<usize, Err<'a>> {
+		for branch in tree.branches.iter() { self.expression(branch)?; }
+		Ok(tree.branches.len())
+	}
+
+	// builtins take priority over user defined objects with the same name
+	fn call<'a> (&mut self, callee: &Node, arguments: &Node, site: &Site) -> Result<(), Err<'a>> {
+		let label = if let (name, "Symbol") = callee.id() { name.to_string() }else { "<anon>".to_string() };
+		if label == "out" && self.body && self.guarded == 0 && arguments.branches.len() == 1 && self.tail_call(&arguments.branches[0], site)? { return Ok(()); }
+		if let Some(index) = LIBRARY.iter().position(|func| *func == label) {
+			let len = self.collection(arguments)?;
+			self.emit(Op::Builtin(index, len), site);
+			return Ok(());
+		}
+		self.expression(callee)?;
+		let len = self.collection(arguments)?;
+		let label = self.constant(Node::string(label));
+		self.emit(Op::Call(len, label), site);
+		Ok(())
+	}
+
+	fn tail_call<'a> (&mut self, tree: &Node, site: &Site) -> Result<bool, Err<'a>> {
+		if ("_", "Call") != tree.id() { return Ok(false); }
+		let label = if let (name, "Symbol") = tree.branches[0].id() { name.to_string() }else { "<anon>".to_string() };
+		if LIBRARY.contains(&label.as_str()) { return Ok(false); }
+
+		self.expression(&tree.branches[0])?;
+		let len = self.collection(&tree.branches[1])?;
+		let label = self.constant(Node::string(label));
+		self.emit(Op::TailCall(len, label), site);
+		Ok(true)
+	}
+
+	fn if_expr<'a> (&mut self, tree: &Node, site: &Site) -> Result<(), Err<'a>> {
+		let mut exits = Vec::new();
+		for if_stat in tree.branches.iter() {
+			self.expression(&if_stat.branches[0])?;
+			let skip = self.emit(Op::JumpIfFalse(0), site);
+			self.expression(&if_stat.branches[1])?;
+			exits.push(self.emit(Op::Jump(0), site));
+			self.patch(skip);
+			if if_stat.branches.len() == 3 {
+				self.expression(&if_stat.branches[2])?;
+				exits.push(self.emit(Op::Jump(0), site));
+		}}
+		let void = self.constant(Node::void());
+		self.emit(Op::Constant(void), site);
+		for exit in exits.into_iter() { self.patch(exit); }
+		Ok(())
+	}
+
+	fn loop_body<'a> (&mut self, contents: &Node, head: usize, site: &Site) -> Result<(), Err<'a>> {
+		self.emit(Op::Arm, site);
+		self.expression(contents)?;
+		self.emit(Op::Collect, site);
+		self.emit(Op::Jump(head), site);
+		Ok(())
+	}
+
+	fn object_expr<'a> (&mut self, tree: &Node, site: &Site) -> Result<(), Err<'a>> {
+		let expr = &tree.branches[1];
+		if expr.id() == ("_", "Combinator") {
+			if ("expect", "Symbol") == expr.branches[0].id() { return self.expect(tree, expr, site); }
+
+			// bind the operand in a new scope and call the target with it
+			let bind = expr.branches[1].validate_args_len(1)?.branches[0].validate_type("Symbol")?;
+			let call = &expr.branches[2];
+			if call.branches.len() < 2 { return Err(Err::new(ErrorClass::ArgMismatch(2, call.branches.len()), call.clone())); }
+
+			self.expression(&tree.branches[0])?;
+			self.emit(Op::EnterScope, site);
+			let name = self.name(&bind);
+			self.emit(Op::Store(name), site);
+			self.emit(Op::Pop, site);
+			self.call(&call.branches[0], &call.branches[1], site)?;
+			self.emit(Op::ExitScope, site);
+			return Ok(());
+		}
+
+		self.expression(&tree.branches[0])?;
+		let field = ("_", "Field") == expr.id();
+		let code = match field {
+			true => compile_block(expr)?,
+			false => compile(expr)?
+		};
+		let rhs = self.constant(Node::new((*expr.signature).clone(), expr.span, Vec::new()));
+		let child = self.child(code);
+		self.emit(Op::Access(rhs, child, field), site);
+		Ok(())
+	}
+
+	fn expect<'a> (&mut self, tree: &Node, expr: &Node, site: &Site) -> Result<(), Err<'a>> {
+		let bind = expr.branches[1].validate_args_len(1)?.branches[0].validate_type("Symbol")?;
+		let handler = &expr.branches[2];
+		if handler.branches.len() < 2 { return Err(Err::new(ErrorClass::ArgMismatch(2, handler.branches.len()), handler.clone())); }
+
+		let kind = self.constant(handler.branches[0].clone());
+		let setup = self.emit(Op::SetupExpect(0, kind), site);
+		self.guarded += 1;
+		self.expression(&tree.branches[0])?;
+		self.guarded -= 1;
+		self.emit(Op::PopBlock, site);
+		let done = self.emit(Op::Jump(0), site);
+
+		// the caught error is on the stack, bind it without leaking into the outer scope
+		self.patch(setup);
+		self.emit(Op::EnterScope, site);
+		let name = self.name(&bind);
+		self.emit(Op::Bind(name), site);
+		self.expression(&handler.branches[1])?;
+		self.emit(Op::ExitScope, site);
+		self.patch(done);
+		Ok(())
+	}
+
+	fn try_expr<'a> (&mut self, tree: &Node, site: &Site) -> Result<(), Err<'a>> {
+		let clauses: Vec<&Node> = tree.branches[1..].iter().filter(|clause| clause.branches.len() == 2).collect();
+		let cleanup = tree.branches[1..].iter().find(|clause| clause.branches.len() == 1);
+
+		// out() in the body or a handler has to come back through the finally block, so no tail calls
+		self.guarded += 1;
+		let setup_finally = cleanup.map(|_| self.emit(Op::SetupFinally(0), site));
+		if clauses.is_empty() { self.expression(&tree.branches[0])?; }
+		else {
+			let mut kinds = Vec::new();
+			for clause in clauses.iter() {
+				let mut accepted = Vec::new();
+				for kind in clause.branches[0].branches[1..].iter() {
+					match kind.id() {
+						(name, "Symbol") | (name, "String") => { accepted.push(Node::string(name.to_string())); },
+						_ => { return Err(Err::new(ErrorClass::TypeMismatch("String", kind.get_type()), kind.clone())); }
+				}}
+				kinds.push(Node::new(NodeClass::Field, [0, 0], accepted));
+			}
+			let kinds = self.constant(Node::new(NodeClass::Field, [0, 0], kinds));
+			let setup = self.emit(Op::SetupTry(0, kinds), site);
+			self.expression(&tree.branches[0])?;
+			self.emit(Op::PopBlock, site);
+			let mut exits = vec![self.emit(Op::Jump(0), site)];
+
+			// the vm resumes at the jump for the clause that matched, with the error value on the stack
+			self.patch(setup);
+			let table: Vec<usize> = clauses.iter().map(|_| self.emit(Op::Jump(0), site)).collect();
+			for (jump, clause) in table.into_iter().zip(clauses.iter()) {
+				self.patch(jump);
+				let bind = clause.branches[0].branches[0].validate_type("Symbol")?;
+				self.emit(Op::EnterScope, site);
+				let name = self.name(&bind);
+				self.emit(Op::Bind(name), site);
+				self.statements(&clause.branches[1])?;
+				self.emit(Op::ExitScope, site);
+				exits.push(self.emit(Op::Jump(0), site));
+			}
+			for exit in exits.into_iter() { self.patch(exit); }
+		}
+		self.guarded -= 1;
+
+		if let (Some(setup), Some(cleanup)) = (setup_finally, cleanup) {
+			self.patch(setup);
+			self.emit(Op::EnterFinally, site);
+			self.expression(&cleanup.branches[0])?;
+			self.emit(Op::EndFinally, site);
+		}
+		Ok(())
+	}
+
+	fn object<'a> (&mut self, tree: &Node) -> Result<Node, Err<'a>> {
+		let code = Arc::new(compile_body(&tree.branches[1])?);
+		Ok(Node::new(NodeClass::Object, tree.span, vec![tree.branches[0].clone(), tree.branches[1].clone(), Node::new(NodeClass::Code(code), [0, 0], Vec::new())]))
+	}
+}
+
+
+// compile a single expression
+pub fn compile<'a> (tree: &Node) -> Result<Chunk, Err<'a>> {
+	let mut compiler = Compiler::new(false);
+	compiler.expression(tree)?;
+	Ok(compiler.chunk)
+}
+
+
+// compile the contents of a field without opening a new scope
+pub fn compile_block<'a> (tree: &Node) -> Result<Chunk, Err<'a>> {
+	let mut compiler = Compiler::new(false);
+	compiler.statements(tree)?;
+	Ok(compiler.chunk)
+}
+
+
+// compile the contents of an object, out() calls in it can be turned into tail calls
+pub fn compile_body<'a> (tree: &Node) -> Result<Chunk, Err<'a>> {
+	let mut compiler = Compiler::new(true);
+	compiler.statements(tree)?;
+	Ok(compiler.chunk)
+}
+
+
+// objects that didn't come from a literal (e.g. built by hand) are compiled on every call
+pub fn code_for<'a> (object: &Node) -> Result<Arc<Chunk>, Err<'a>> {
+	if let Some(code) = object.branches.get(2) {
+		if let NodeClass::Code(ref chunk) = *code.signature { return Ok(chunk.clone()); }
+	}
+	Ok(Arc::new(compile_body(&object.branches[1])?))
+}
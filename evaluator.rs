@@ -1,15 +1,110 @@
-use std::sync::{ Arc, RwLock };
-use std::{ fs, process };
+use std::sync::{ Arc, OnceLock, RwLock };
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::collections::HashMap;
+use std::hash::{ BuildHasherDefault, Hasher };
+use std::time::{ Duration, Instant };
+use std::{ env, fs, process };
 use crate::parser::{ ParserConfig, parse };
-use crate::utils::node::{ NodeClass, Node };
-use crate::utils::error::{ ErrorClass, Err };
-use crate::utils::builtin_funcs::search_library;
+use crate::utils::node::{ NodeClass, Node, intern, live_nodes };
+use crate::utils::error::{ ErrorClass, ErrorFormat, Err, register_source };
+use crate::compiler::compile;
+use crate::vm::run;
+
+
+pub const CALL_LIMIT: usize = 1000;
+const CLOCK_INTERVAL: usize = 1024; // steps between deadline checks
+// room a handler gets to run in after catching a LimitExceeded, given once per evaluation
+const GRACE_STEPS: usize = 10000;
+const GRACE_NODES: usize = 10000;
+const GRACE_TIME: Duration = Duration::from_millis(100);
+const MODULE_EXTENSION: &str = "ky";
+
+// every module imported by this process, so each file is only evaluated once
+static MODULES: RwLock<Vec<(PathBuf, Node)>> = RwLock::new(Vec::new());
+
+// set by the terminal's SIGINT handler, running code stops at its next step
+pub static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+
+// caps on a single evaluation for code that can't be trusted to halt, None means unbounded
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+	pub steps: Option<usize>, // vm instructions
+	pub timeout: Option<Duration>,
+	pub nodes: Option<usize> // approximate count of live nodes
+}
+
+// usage counted against the limits, restarted for every evaluated file except imports
+// clones share the step count, so imported modules run on their importer's budget
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+	pub limits: Limits,
+	steps: Arc<AtomicUsize>,
+	graced: Arc<AtomicBool>, // a handler has caught a LimitExceeded, the limits are extended by the grace allowance
+	base: usize, // live nodes when the evaluation started
+	deadline: Option<Instant>
+}
+
+impl <'a>Budget {
+	pub fn start (&mut self) {
+		self.steps.store(0, Ordering::Relaxed);
+		self.graced.store(false, Ordering::Relaxed);
+		self.base = live_nodes();
+		self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+	}
+	
+	// a handler that caught a LimitExceeded runs on the grace allowance, the total budget is never reset
+	pub fn grace (&self) { self.graced.store(true, Ordering::Relaxed); }
+	
+	// the grace allowance is used up too, a LimitExceeded raised now passes every handler
+	pub fn spent (&self) -> bool {
+		if !self.graced.load(Ordering::Relaxed) { return false; }
+		self.limits.steps.is_some_and(|max| self.steps.load(Ordering::Relaxed) > max + GRACE_STEPS)
+			|| self.limits.nodes.is_some_and(|max| live_nodes().saturating_sub(self.base) > max + GRACE_NODES)
+			|| self.deadline.is_some_and(|deadline| Instant::now() >= deadline + GRACE_TIME)
+	}
+	
+	pub fn tick (&self) -> Result<(), Err<'a>> {
+		// the flag is only written to when it's set, a swap on every step would cost more than the step
+		if INTERRUPT.load(Ordering::Relaxed) && INTERRUPT.swap(false, Ordering::Relaxed) { return Err(Err::new(ErrorClass::Signal("KeyboardInterrupt"), Node::void())); }
+		if self.limits.steps.is_none() && self.deadline.is_none() { return Ok(()); } // nothing needs the count
+		let steps = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+		let graced = self.graced.load(Ordering::Relaxed);
+		if let Some(max) = self.limits.steps {
+			if steps > max + if graced { GRACE_STEPS }else { 0 } { return Err(Err::new(ErrorClass::LimitExceeded("step"), Node::number(max as f32))); }
+		}
+		if let Some(deadline) = self.deadline {
+			let deadline = if graced { deadline + GRACE_TIME }else { deadline };
+			if steps.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline { return Err(Err::new(ErrorClass::LimitExceeded("time"), Node::void())); }
+		}
+		Ok(())
+	}
+	
+	// checks there is room for this many more nodes, nodes freed since the start are given back
+	pub fn allocate (&self, nodes: usize) -> Result<(), Err<'a>> {
+		if let Some(max) = self.limits.nodes {
+			let grace = if self.graced.load(Ordering::Relaxed) { GRACE_NODES }else { 0 };
+			if live_nodes().saturating_sub(self.base) + nodes > max + grace { return Err(Err::new(ErrorClass::LimitExceeded("memory"), Node::number(max as f32))); }
+		}
+		Ok(())
+	}
+}
 
 
 #[derive(Clone)]
 pub struct Env {
 	pub trace: Vec<(NodeClass, [usize; 2])>,
     pub depth: usize,
+    pub calls: usize, // nested object calls, tail calls don't count
+    pub call_limit: usize,
+    pub budget: Budget,
+    pub error_format: ErrorFormat,
+    pub file: Arc<str>, // name and wrapped source of the code being evaluated, used to locate caught errors
+    pub source: Arc<str>,
+    pub search: Arc<Vec<PathBuf>>, // directories relative imports are looked for in
+    pub loading: Arc<Vec<(PathBuf, String)>>, // files whose evaluation led to this one, outermost first
+    pub exports: Arc<RwLock<Vec<Node>>>, // names declared by export statements, shared by clones
     pub import: NameSpace,
     pub data: Vec<NameSpace>
 }
@@ -19,73 +114,189 @@ impl Env {
     	Env {
 	        trace: Vec::new(),
 	        depth: 0,
+	        calls: 0,
+	        call_limit: CALL_LIMIT,
+	        budget: Budget::default(),
+	        error_format: ErrorFormat::Text,
+	        file: Arc::from(""),
+	        source: Arc::from(""),
+	        search: Arc::new(search_path("")),
+	        loading: Arc::new(Vec::new()),
+	        exports: Arc::new(RwLock::new(Vec::new())),
 	        import: NameSpace::new(),
 	        data: vec![NameSpace::new()]
 		}
 	}
+	
+	pub fn with_limits (limits: Limits) -> Env {
+		let mut env = Env::create();
+		env.budget.limits = limits;
+		env
+	}
+	
+	// same settings and budget without any bindings
+	pub fn fresh (&self) -> Env {
+		let mut env = Env::create();
+		env.budget = self.budget.clone();
+		env.call_limit = self.call_limit;
+		env.error_format = self.error_format;
+		env.search = self.search.clone();
+		env
+	}
+}
+
+// interned ids are small consecutive integers, one multiply spreads them well enough and costs far less than the default hasher
+#[derive(Default)]
+struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+	fn finish (&self) -> u64 { self.0 }
+	fn write (&mut self, bytes: &[u8]) { for byte in bytes.iter() { self.write_u64(*byte as u64); } }
+	fn write_u64 (&mut self, id: u64) { self.0 = (self.0.rotate_left(5) ^ id).wrapping_mul(0x9e3779b97f4a7c15); }
+	fn write_usize (&mut self, id: usize) { self.write_u64(id as u64); }
+}
+
+#[derive(Debug, Default)]
+struct SymbolTable {
+	index: HashMap<usize, usize, BuildHasherDefault<IdHasher>>, // interned key -> slot of its first binding
+	ids: Vec<usize>,
+	keys: Vec<Node>,
+	values: Vec<Node>
+}
+
+impl SymbolTable {
+	fn insert (&mut self, id: usize, key: Node, value: Node) {
+		self.index.entry(id).or_insert(self.keys.len());
+		self.ids.push(id);
+		self.keys.push(key);
+		self.values.push(value);
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct NameSpace { // double vec allows easy conversion to and from nodes
-	keys: Arc<RwLock<Vec<Node>>>,
- 	values: Arc<RwLock<Vec<Node>>>
+	table: Arc<RwLock<SymbolTable>>
 }
 
 impl <'a>NameSpace {
-	pub fn new () -> NameSpace { NameSpace{ keys: Arc::new(RwLock::new(Vec::new())), values: Arc::new(RwLock::new(Vec::new())) } }
+	pub fn new () -> NameSpace { NameSpace{ table: Arc::new(RwLock::new(SymbolTable::default())) } }
 	
-	fn from (keys: Node, values: Node) -> Result<NameSpace, Err<'a>> {
+	pub fn from (keys: Node, values: Node) -> Result<NameSpace, Err<'a>> {
 		let _ = keys.validate_args_len(values.branches.len() as usize)?;
-		Ok(NameSpace {
-			keys: Arc::new(RwLock::new(keys.branches.into_iter().map(|k| *k).collect())),
-			values: Arc::new(RwLock::new(values.branches.into_iter().map(|k| *k).collect()))
-		})
+		let mut table = SymbolTable::default();
+		for (key, value) in keys.branches.iter().zip(values.branches.iter()) { table.insert(key.key_id(), key.clone(), value.clone()); }
+		Ok(NameSpace { table: Arc::new(RwLock::new(table)) })
+	}
+	
+	// scope of an object call, the arguments are moved in and [@] links to the caller's scope
+	pub fn frame (params: &Node, arguments: Vec<Node>, depth: usize) -> Result<NameSpace, Err<'a>> {
+		static LINK: OnceLock<(usize, Node)> = OnceLock::new();
+		let _ = params.validate_args_len(arguments.len())?;
+		let (link_id, link) = LINK.get_or_init(|| (intern("[@]"), Node::symbol("[@]".to_string())));
+		let mut table = SymbolTable::default();
+		table.index.reserve(arguments.len() + 1);
+		for (key, value) in params.branches.iter().zip(arguments) { table.insert(key.key_id(), key.clone(), value); }
+		table.insert(*link_id, link.clone(), Node::new(NodeClass::ObjectInst("<extern_link>".to_string()), [0, 0], vec![Node::number(depth as f32)]));
+		Ok(NameSpace { table: Arc::new(RwLock::new(table)) })
 	}
 	
 	pub fn as_node (&self, label: Node) -> Node {
-		let keys = Node::new(NodeClass::Field, [0, 0], self.keys.read().unwrap().clone());
-		let values = Node::new(NodeClass::Field, [0, 0], self.values.read().unwrap().clone());
+		let table = self.table.read().unwrap();
+		let keys = Node::new(NodeClass::Field, [0, 0], table.keys.clone());
+		let values = Node::new(NodeClass::Field, [0, 0], table.values.clone());
 		let obj_name = label.into_string().unwrap_or_else(|_| "_".to_string());
 		
 		Node::new(NodeClass::ObjectInst(obj_name), [0, 0], vec![keys, values])
 	}
 	
-	fn find_index (&self, symbol: Node) -> Result<usize, Err<'a>> {
-		let lookup = symbol.clone();
-		match self.keys.read().unwrap().iter().position(|key| *key.signature == *lookup.signature) {
-			Some(result) => Ok(result),
-			None => {
-				let ref_name = if let (name, "Symbol") = symbol.id() { name.to_string() }else { "_".to_string() };
-				Err(Err::new(ErrorClass::VoidReference(ref_name), symbol))
-		}}
+	fn void_ref (symbol: &Node) -> Err<'a> {
+		let ref_name = if let (name, "Symbol") = symbol.id() { name.to_string() }else { "_".to_string() };
+		Err::new(ErrorClass::VoidReference(ref_name), symbol.clone())
 	}
 
-	pub fn get (&self, key: Node) -> Result<Node, Err<'a>> {
-		let index = self.find_index(key)?;
-		Ok(self.values.read().unwrap()[index].clone())
-	}
+	pub fn get (&self, key: Node) -> Result<Node, Err<'a>> { self.lookup(key.key_id(), &key) }
 	
 	pub fn set (&self, key: Node, value: Node) -> Result<Node, Err<'a>> {
-		let index = self.find_index(key.clone());
-		
-		match index {
-			Ok(idx) => { // update existing variable
-				self.values.write().unwrap()[idx] = value.clone();
-				Ok(value)
+		self.assign(key.key_id(), &key, value.clone());
+		Ok(value)
+	}
+	
+	// variants of get/ set used by the vm, keys are interned when the code is compiled
+	pub fn lookup (&self, id: usize, key: &Node) -> Result<Node, Err<'a>> {
+		let table = self.table.read().unwrap();
+		match table.index.get(&id) {
+			Some(slot) => Ok(table.values[*slot].clone()),
+			None => Err(NameSpace::void_ref(key))
+		}
+	}
+
+	// slots resolved by the parser are checked before use, scopes that don't match fall back to hashing
+	pub fn lookup_slot (&self, slot: usize, id: usize, key: &Node) -> Result<Node, Err<'a>> {
+		{
+			let table = self.table.read().unwrap();
+			if table.ids.get(slot) == Some(&id) && table.index.get(&id) == Some(&slot) { return Ok(table.values[slot].clone()); }
+		}
+		self.lookup(id, key)
+	}
+
+	pub fn find (&self, id: usize) -> Option<Node> {
+		let table = self.table.read().unwrap();
+		table.index.get(&id).map(|slot| table.values[*slot].clone())
+	}
+
+	pub fn assign (&self, id: usize, key: &Node, value: Node) {
+		let mut table = self.table.write().unwrap();
+		match table.index.get(&id) {
+			Some(slot) => { // update existing variable
+				let slot = *slot;
+				table.values[slot] = value;
 			},
-			Err(e) => {
-				if let ErrorClass::VoidReference(_) = *e.class { // create new variable
-					self.keys.write().unwrap().push(key.clone());
-					self.values.write().unwrap().push(value.clone());
-					Ok(value)
-				}
-				else { Err(e) }
+			None => { table.insert(id, Node::new((*key.signature).clone(), key.span, Vec::new()), value); } // create new variable
+		}
+	}
+
+	pub fn assign_slot (&self, slot: usize, id: usize, key: &Node, value: Node) {
+		{
+			let mut table = self.table.write().unwrap();
+			if table.ids.get(slot) == Some(&id) && table.index.get(&id) == Some(&slot) {
+				table.values[slot] = value;
+				return;
 		}}
+		self.assign(id, key, value);
+	}
+
+	// append bindings from another namespace that aren't already defined here
+	pub fn extend (&self, other: &NameSpace) {
+		if Arc::ptr_eq(&self.table, &other.table) { return; }
+		let extern_table = other.table.read().unwrap();
+		let mut table = self.table.write().unwrap();
+		for slot in 0..extern_table.keys.len() {
+			let id = extern_table.ids[slot];
+			if table.index.contains_key(&id) { continue; }
+			table.insert(id, extern_table.keys[slot].clone(), extern_table.values[slot].clone());
+		}
+	}
+	
+	// names of every binding, used for completion in the terminal
+	pub fn names (&self) -> Vec<String> {
+		let table = self.table.read().unwrap();
+		table.keys.iter().map(|key| key.decode()).collect()
+	}
+	
+	// drop every binding for the key, returns false if there wasn't one
+	pub fn remove (&self, id: usize) -> bool {
+		let mut table = self.table.write().unwrap();
+		if !table.index.contains_key(&id) { return false; }
+		let old = std::mem::take(&mut *table);
+		for slot in 0..old.keys.len() {
+			if old.ids[slot] != id { table.insert(old.ids[slot], old.keys[slot].clone(), old.values[slot].clone()); }
+		}
+		true
 	}
 	
 	pub fn show(&self) -> String {
-		let key_reader = self.keys.read().unwrap();
-		let value_reader = self.values.read().unwrap();
+		let table = self.table.read().unwrap();
+		let (key_reader, value_reader) = (&table.keys, &table.values);
 		if key_reader.len() == 0 { return String::from("nothing to show"); }
 		
 		let mut string = String::new();
@@ -97,8 +308,8 @@ impl <'a>NameSpace {
 	}
 	
 	pub fn show_modules(&self) {
-		let key_reader = self.keys.read().unwrap();
-		let value_reader = self.values.read().unwrap();
+		let table = self.table.read().unwrap();
+		let (key_reader, value_reader) = (&table.keys, &table.values);
 		if key_reader.len() == 0 {
 			println!("nothing to show");
 			return;
@@ -122,337 +333,283 @@ impl <'a>NameSpace {
 
 // load_file and eval_file required for the ext() function user interface
 // i'd rather not import anything from crate::main so i'll just put them here
-pub fn eval_file (env: Env, mut source_file: String, halt_on_err: bool) {
-	source_file = format!("{{{}}}$", source_file); // ensure lookahead doesn't hit EOF
-	let src = source_file.clone();
-    let cfg = ParserConfig::create(&src);
-    
-    let result = match parse(cfg.clone()) {
-    	Ok(()) => { cfg.data.write().unwrap().stack.pop().unwrap() },
-    	Err(e) => {
-    		let trace: Vec<(NodeClass, [usize; 2])> = cfg.data.read().unwrap().stack.iter().map(|elem| (*elem.signature.clone(), elem.span)).collect();
-    		e.throw(source_file.clone(), trace, halt_on_err);
-    		return;
-    }};
-    
-	match evaluate(env.clone(), result) {
-    	Ok(_) => (),
-    	Err(e) => { e.throw(source_file, env.trace, halt_on_err); }
-    };
-}
+// name is only used to label diagnostics
+pub fn eval_file (env: Env, name: &str, source_file: String, halt_on_err: bool) -> Option<Node> { eval_source(env, name, source_file, false, halt_on_err) }
 
 
-pub fn load_file (path: String, halt_on_err: bool) -> String {
-	fs::read_to_string(&path).unwrap_or_else( |err| {
-		eprintln!("[-] an error occurred while opening the file {}: {}", &path, err);
-		if halt_on_err { process::exit(1); }else { return String::new(); }
-	})
+// every source is registered so that spans from its code can be told apart from those of other files and earlier terminal inputs
+fn eval_source (mut env: Env, name: &str, mut source_file: String, module: bool, halt_on_err: bool) -> Option<Node> {
+	if source_file.starts_with("#!") { // shebang, the line is kept empty so positions don't move
+		let end = source_file.find('\n').unwrap_or(source_file.len());
+		source_file.replace_range(..end, "");
+	}
+	source_file = format!("{{{}}}$", source_file); // ensure lookahead doesn't hit EOF
+	let mut result = match parse_source(&source_file) {
+		Ok(tree) => tree,
+		Err(errors) => {
+			for e in errors.iter() { e.report(env.error_format, source_file.clone(), name, Vec::new(), false); }
+			if halt_on_err { process::exit(1); }
+			return None;
+	}};
+	
+	offset_spans(&mut result, register_source(name, &source_file));
+	env.file = Arc::from(name);
+	env.source = Arc::from(source_file.as_str());
+	if !module { env.budget.start(); } // an import keeps counting against its importer's limits
+	match evaluate(&mut env, &result) {
+		Ok(value) => Some(value),
+		Err(e) if ErrorClass::Signal("Exit") == *e.class => { process::exit(e.cause.into_number().unwrap_or(0.0) as i32); },
+		Err(e) => {
+			e.report(env.error_format, source_file, name, env.trace.clone(), halt_on_err);
+			None
+	}}
 }
 
 
-fn handle_error<'a> (mut env: Env, val: Result<Node, Err<'a>>, expr: Node) -> Result<Node, Err<'a>> {
-	let err = match val {
-		Ok(node) => { return Ok(node); },
-		Err(e) => { if e.to_node()?.id() != expr.branches[2].branches[0].id() { return Err(e); }else { e.to_node()? } }
-	};
-	
-	env.data.push(env.data[env.depth].clone());
-	env.depth += 1;  // prevent binding from leaking into outer scope
-
-	let bind = expr.branches[1].validate_args_len(1)?.branches[0].validate_type("Symbol")?;
-	env.data[env.depth].set(bind.clone(), err);
-	let result = evaluate(env.clone(), *expr.branches[2].branches[1].clone());
-	// println!("handled error: {:?}", result);
-	
-	env.depth -= 1;
-	env.data.pop();
-	return result;
+// every syntax error in the source, in the order they were found
+pub fn parse_source<'a> (source: &'a str) -> Result<Node, Vec<Err<'a>>> {
+	let cfg = ParserConfig::create(source);
+	let result = parse(cfg.clone());
+	let mut errors = cfg.errors.write().unwrap().split_off(0);
+	match result {
+		Ok(()) if errors.is_empty() => Ok(cfg.data.write().unwrap().stack.pop().unwrap_or_else(Node::void)),
+		Ok(()) => Err(errors),
+		Err(e) => {
+			errors.push(e);
+			Err(errors)
+	}}
 }
 
 
-fn add_extension<'a> (mut env: Env, tree: Node) -> Result<Node, Err<'a>> {
-	let path = tree.branches[1].branches[0].into_string()?;
-	let source = load_file(path.clone(), false);
-	if source == String::new() { return Err(Err::new(ErrorClass::File("file not found", path), tree)); }
-	
-	let guest_env = Env::create();
-	eval_file(guest_env.clone(), source, false);
-	
-	let file_name = path.split("/").collect::<Vec<&str>>().pop().unwrap(); // get last arg in path
-	let mut label = file_name.split(".").nth(0).unwrap(); // remove extension
-	
-	env.import.set(Node::symbol(label.to_string()), guest_env.data[0].as_node(Node::symbol("<extension>".to_string())));
-	return Ok(Node::void());
+pub fn load_file<'a> (path: &str) -> Result<String, Err<'a>> {
+	fs::read_to_string(path).map_err(|err| Err::file(format!("could not be read: {}", err), &Node::string(path.to_string())))
 }
 
 
-pub fn evaluate<'a> (mut env: Env, tree: Node) -> Result<Node, Err<'a>> {
-	env.trace.push((*tree.signature.clone(), tree.span));
-    let result = match tree.id() {
-        (_, "OperatorExpression") => oper_expr_eval(env.clone(), tree)?,
-        ("_", "Field") => evaluate_scope(env.clone(), env.data[env.depth].clone(), tree)?,
-        ("_", "List") =>  evaluate_collection(env.clone(), *tree.branches[0].clone())?,
-        ("_", "Parenthesis") =>  *evaluate_collection(env.clone(), *tree.branches[0].clone())?.branches[0].branches[0].clone(),
-        ("_", "Call") =>  call_eval(env.clone(), tree)?,
-        ("_", "IfExpression") =>  if_eval(env.clone(), tree)?,
-        (_, "LoopExpression") =>  loop_eval(env.clone(), tree)?,
-        ("_", "Combinator") => {
-        	return Err(Err::new(ErrorClass::CustomError(format!("Cannot invoke '{}' combinator, no target specified", tree.branches[0].into_string()?)), tree));
-        },
-        ("[!]", "Symbol") => env.data[env.depth].as_node(Node::symbol("<ident>".to_string())),
-        (_, "Symbol") => {
-        	if let Ok(var) = env.import.get(tree.clone()) { var }else { env.data[env.depth].get(tree)? } // check imported first
-        },
-        _ => tree
-	};
-	env.trace.pop();
-	Ok(result)
+// the script's directory followed by the directories listed in KYLU_PATH
+pub fn search_path (script: &str) -> Vec<PathBuf> {
+	let mut dirs = vec![match Path::new(script).parent() {
+		Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+		_ => PathBuf::from(".")
+	}];
+	if let Some(paths) = env::var_os("KYLU_PATH") { dirs.extend(env::split_paths(&paths)); }
+	dirs
 }
 
 
-fn oper_expr_eval<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
-	// <- and : operators cannot act on literals, evaluation must be handled seperately
-    if ("<-", "OperatorExpression") == tree.id() {
-    	let value = evaluate(env.clone(), *tree.branches[1].clone())?;
-    	return env.data[env.depth].set(tree.branches[0].validate_type("Symbol")?, value);
-    }
-    if (":", "OperatorExpression") == tree.id() { return object_expr_eval(env.clone(), tree); }
-
-    let l_op = evaluate(env.clone(), *tree.branches[0].clone())?;
-    let r_op = evaluate(env.clone(), *tree.branches[1].clone())?;
-    let oper = if let (op, "OperatorExpression") = tree.id() { op } else { "_" };
-    
-    match (l_op.get_type(), r_op.get_type()) {
-        ("Number", "Number") => {
-        	let num1 = l_op.into_number()?;
-        	let num2 = r_op.into_number()?;
-            match oper {
-                "+" => Ok(Node::number(num1+num2)),
-                "-" => Ok(Node::number(num1-num2)),
-                "*" => Ok(Node::number(num1*num2)),
-                "/" => Ok(Node::number(num1/num2)),
-                "%" => Ok(Node::number(num1%num2)),
-                "^" => Ok(Node::number(num1.powf(num2))),
-                
-                ">" => Ok(Node::boolean(num1>num2)),
-                "<" => Ok(Node::boolean(num1<num2)),
-                "=" => Ok(Node::boolean(num1==num2)),
-                _ => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), "Number", "Number"), tree.clone()))
-        }},
-        ("String", "String") => {
-        	let str1 = l_op.into_string()?;
-        	let str2 = r_op.into_string()?;
-        	match oper {
-        		"=" => Ok(Node::boolean(str1 == str2)),
-        		_ => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), "String", "String"), tree.clone()))
-        }},
-        // operation between list and any other type
-        ("List", _) | (_, "List") => {
-        	let (list, other) = match l_op.get_type() {
-        		"List" => (l_op, r_op),
-        		_ => (r_op, l_op)
-        	};
-        	
-        	match oper {
-        		"=" => Ok(Node::boolean(list==other)),
-        		"+" => {
-        			let mut args = list.branches[0].branches.clone();
-        			args.push(Box::new(other));
-        			Ok(Node::new(NodeClass::List, [0, 0], vec![Node::new_boxed(NodeClass::Field, [0, 0], args)]))
-        		},
-        		_ => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), "List", other.get_type()), tree.clone()))
-        }},
-        (l, r) => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), l, r), tree.clone()))
-	}
+fn offset_spans (tree: &mut Node, base: usize) {
+	if tree.span != [0, 0] { tree.span = [tree.span[0] + base, tree.span[1] + base]; } // [0, 0] marks nodes without a position
+	if tree.branches.is_empty() { return; }
+	for branch in tree.branches_mut().iter_mut() { offset_spans(branch, base); }
 }
 
 
-fn evaluate_collection<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
-    let mut evaluated: Vec<Node> = Vec::new();
-    for branch in tree.branches.iter() { evaluated.push(evaluate(env.clone(), *branch.clone())?); }
-    Ok(Node::new(NodeClass::List, tree.span, vec![Node::new(NodeClass::Field, [0, 0], evaluated)]))
+// first match in the importing file's directory or on the search path, the extension can be left out
+fn resolve_module (env: &Env, path: &str) -> Option<PathBuf> {
+	let importer = Path::new(&*env.file);
+	let mut dirs: Vec<PathBuf> = match (importer.is_file(), importer.parent()) {
+		(true, Some(dir)) => vec![dir.to_path_buf()],
+		_ => Vec::new() // the terminal, relative to the search path only
+	};
+	dirs.extend(env.search.iter().cloned());
+	let candidates: Vec<PathBuf> = match Path::new(path).is_absolute() {
+		true => vec![PathBuf::from(path)],
+		false => dirs.iter().map(|dir| dir.join(path)).collect()
+	};
+	for candidate in candidates.into_iter() {
+		let mut options = vec![candidate.clone()];
+		if candidate.extension().is_none() { options.push(candidate.with_extension(MODULE_EXTENSION)); }
+		if let Some(file) = options.into_iter().find(|option| option.is_file()) { return file.canonicalize().ok(); }
+	}
+	None
 }
 
 
-fn evaluate_scope<'a> (mut env: Env, scope: NameSpace, tree: Node) -> Result<Node, Err<'a>> {
-	let mut expr = Ok(Node::void());
-    env.depth += 1;
-    env.data.push(scope); 
-    for branch in tree.branches.iter() {
-    	expr = evaluate(env.clone(), *branch.clone());
-    	match expr {
-    		Err(_) => { break; },
-    		Ok(_) => ()
-    }}
-    env.depth -= 1;
-    env.data.pop();
-    expr
+// an instance holding the module's global bindings, labelled with the file's stem
+pub fn import_module<'a> (env: &Env, path: &str) -> Result<Node, Err<'a>> {
+	let file = match resolve_module(env, path) {
+		Some(file) => file,
+		None => { return Err(Err::new(ErrorClass::File("was not found on the module search path".to_string(), path.to_string()), Node::string(path.to_string()))); }
+	};
+	let name = display_path(&file);
+	if let Some(start) = env.loading.iter().position(|(loading, _)| *loading == file) {
+		let mut chain: Vec<String> = env.loading[start..].iter().map(|(_, name)| name.clone()).collect();
+		chain.push(name);
+		let cause = Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], chain.iter().map(|name| Node::string(name.clone())).collect())]);
+		return Err(Err::new(ErrorClass::File("forms an import cycle".to_string(), chain.join(" -> ")), cause));
+	}
+	if let Some((_, module)) = MODULES.read().unwrap().iter().find(|(cached, _)| *cached == file) { return Ok(module.clone()); }
+	
+	let source = match fs::read_to_string(&file) {
+		Ok(source) => source,
+		Err(_) => { return Err(Err::new(ErrorClass::File("could not be read".to_string(), path.to_string()), Node::string(path.to_string()))); }
+	};
+	let mut guest = env.fresh();
+	let mut loading = (*env.loading).clone();
+	loading.push((file.clone(), name.clone()));
+	guest.loading = Arc::new(loading);
+	// the module's own errors have already been reported under its name
+	if eval_source(guest.clone(), &name, source, true, false).is_none() {
+		return Err(Err::new(ErrorClass::File("could not be imported".to_string(), path.to_string()), Node::string(path.to_string())));
+	}
+	
+	let stem = file.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+	let module = public_bindings(&guest, stem)?;
+	MODULES.write().unwrap().push((file, module.clone()));
+	Ok(module)
 }
 
 
-fn object_expr_eval<'a> (mut env: Env, tree: Node) -> Result<Node, Err<'a>> {
-	let expr = *tree.branches[1].clone();
-	let operand = evaluate(env.clone(), *tree.branches[0].clone()); // result type
-	
-	if expr.id() == ("_", "Combinator") {
-		let object = if ("expect", "Symbol") == expr.branches[0].id() { return handle_error(env.clone(), operand, expr.clone()); }else { operand? };
-		
-		env.data.push(env.data[env.depth].clone());
-		env.depth += 1;  // prevent binding from leaking into outer scope
-		
-		let bind = expr.branches[1].branches[0].validate_type("Symbol")?;
-		env.data[env.depth].set(bind, object)?;
-		let call = Node::new_boxed(NodeClass::Call, expr.span, expr.branches[2].branches.clone());
-		println!("{}", call.show());
-		let result = call_eval(env.clone(), call);
-
-		env.depth -= 1;
-		env.data.pop();
-		return result;
-	}
-	
-	let object = operand?;
-	
-	match object.id() {
-		("_", "List") => {
-			let slice = *evaluate(env.clone(), expr)?.branches[0].clone();
-			let array = *object.branches[0].clone();
-			match slice.branches.len() {
-				1 => {
-					let index = slice.branches[0].into_number()?;
-					if index as usize >= array.branches.len() { return Err(Err::new(ErrorClass::IndexError(index, object.decode()), object)); }
-					return Ok(*array.branches[index as usize].clone());
-				},
-				2 => {
-					let start = array.branches[0].into_number()?;
-					let stop = array.branches[1].into_number()?;
-
-					if start > stop { return Err(Err::new(ErrorClass::IndexError(start, object.decode()), object)); }
-					if start < 0.0 { return Err(Err::new(ErrorClass::IndexError(start, object.decode()), object)); }
-					if stop as usize >= object.branches.len() { return Err(Err::new(ErrorClass::IndexError(stop, object.decode()), object)); }
-
-					return Ok(Node::new_boxed(NodeClass::List, tree.span, array.branches[start as usize..stop as usize].to_vec()));
-				},
-				_ => { return Err(Err::new(ErrorClass::IndexError(2.0, object.decode()), slice)); }
-			}
-			
-		},
-		(inst_of, "ObjectInstance") => {
-			let operations = match *expr.signature {
-				NodeClass::Field => expr,
-				_ => Node::new(NodeClass::Field, expr.span, vec![expr])
-			};
-			let mut result = Ok(Node::void());
-			let target_ns = if inst_of == "<extern_link>" { env.data[object.branches[0].into_number()? as usize].clone() }
-			else { NameSpace::from(*object.branches[0].clone(), *object.branches[1].clone())? };
-			
-			// operations on object instances are allowed to access global variables
-			let extern_keys = env.data[env.depth].keys.read().unwrap().clone();
-			let extern_vals = env.data[env.depth].values.read().unwrap().clone();
-			target_ns.keys.write().unwrap().extend(extern_keys);
-			target_ns.values.write().unwrap().extend(extern_vals);
-			
-			result = evaluate_scope(env.clone(), target_ns, operations);
-			// I could use .unwrap_or_else() but this way feels more intuitive to me
-			match result {
-				Ok(ref val) => { 
-					if ("<ident>", "ObjectInstance") == val.id() { result = Ok(Node::new_boxed(NodeClass::ObjectInst(inst_of.to_string()), val.span, val.branches.clone())); }
-				},
-				Err(ref e) => {
-					if *e.class == ErrorClass::Signal("StopFunction") { result = Ok(e.cause.clone()); }
-			}}
-			return result;
-		},
-		(_, _) => Err(Err::new(ErrorClass::UndefinedOperation(":".to_string(), object.get_type(), expr.get_type()), object))
+// the exported names if the module declared any, otherwise every global binding
+fn public_bindings<'a> (guest: &Env, label: String) -> Result<Node, Err<'a>> {
+	let exports = guest.exports.read().unwrap();
+	if exports.is_empty() { return Ok(guest.data[0].as_node(Node::string(label))); }
+	let mut fields: Vec<(String, Node)> = Vec::new();
+	for name in exports.iter() {
+		if fields.iter().any(|(key, _)| *key == name.decode()) { continue; } // exported twice
+		fields.push((name.decode(), guest.data[0].get(name.clone())?));
 	}
+	Ok(Node::instance(&label, fields))
 }
 
 
-fn call_eval<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
-	let stdlib_search = search_library(env.clone(), tree.clone())?;
-	let mut object_return = Ok(Node::void());
-	
-	if stdlib_search.id() != ("_", "Void") { object_return = Ok(stdlib_search); }
-	else {
-		let object = evaluate(env.clone(), *tree.branches[0].clone())?.validate_type("Object")?;
-		let arguments = evaluate_collection(env.clone(), *tree.branches[1].clone())?;
-		let func_ns = NameSpace::from(*object.branches[0].clone(), *arguments.branches[0].clone())?;
-		// create link to function scope allowing inner methods to access it
-		func_ns.set(Node::symbol("[@]".to_string()), Node::new(NodeClass::ObjectInst("<extern_link>".to_string()), [0, 0], vec![Node::number(env.depth as f32)]))?;
-		object_return = evaluate_scope(env.clone(), func_ns, *object.branches[1].clone());
-	}
-    
-    match object_return {
-    	Ok(ref val) => { 
-    		if ("<ident>", "ObjectInstance") == val.id() { // set instance's name to caller's name
-				let inst_of = if let (label, "Symbol") = tree.branches[0].id() { label.to_string() }else { "<anon>".to_string() };
-				return Ok(Node::new_boxed(NodeClass::ObjectInst(inst_of), val.span, val.branches.clone()));
-			}
-			else { return object_return; }
-		},
-		Err(ref e) => { 
-			if ErrorClass::Signal("StopFunction") == *e.class { return Ok(e.cause.clone()); }
-			else { return object_return; }
-	}}
+// relative to the working directory when the file is below it, so diagnostics stay short
+pub fn display_path (file: &Path) -> String {
+	let relative = env::current_dir().ok().and_then(|cwd| file.strip_prefix(cwd).ok().map(|path| path.to_path_buf()));
+	relative.unwrap_or_else(|| file.to_path_buf()).to_string_lossy().to_string()
 }
 
 
-fn if_eval<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
-    for if_stat in tree.branches.iter() {
-        if evaluate(env.clone(), *if_stat.branches[0].clone())?.into_boolean()? {
-			return evaluate_scope(env.clone(), env.data[env.depth].clone(), *if_stat.branches[1].clone()); 
-		}
-        else if if_stat.branches.len() == 3 {
-        	return evaluate_scope(env.clone(), env.data[env.depth].clone(), *if_stat.branches[2].clone());
-    }}
-    Ok(Node::void())
+// compile the tree and run it, on failure the trace is left in env
+pub fn evaluate<'a> (env: &mut Env, tree: &Node) -> Result<Node, Err<'a>> {
+	env.trace.clear();
+	INTERRUPT.store(false, Ordering::Relaxed); // ignore presses made before this evaluation
+	let code = compile(tree)?;
+	let result = run(env, &code);
+	env.trace.reverse();
+	result
 }
 
 
-fn loop_eval<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
-    match tree.id() {
-        ("cond", "LoopExpression") => {
-            let condition = *tree.branches[0].branches[0].clone();
-            let contents = *tree.branches[1].clone();
-            
-            let mut comp: Vec<Node> = Vec::new();
-            let mut temp = Ok(Node::void());
-            while evaluate(env.clone(), condition.clone())?.into_boolean()? {
-                temp = evaluate_scope(env.clone(), env.data[env.depth].clone(), contents.clone());
-                match temp {
-                	Err(ref e) => { 
-                		if ErrorClass::Signal("StopIteration") == *e.class { break; }
-                		if ErrorClass::Signal("ResetIteration") == *e.class { continue; }
-                		else { return temp; }
-                	},
-                	Ok(val) => { comp.push(val); }
-            }}
-        	Ok(Node::new(NodeClass::List, tree.span, vec![Node::new(NodeClass::Field, tree.span, comp)]))
-        },
-        ("iter", "LoopExpression") => {
-            let index = *tree.branches[0].branches[0].clone(); // name taken by each index
-            let iterator = evaluate(env.clone(), *tree.branches[0].branches[1].clone())?.validate_type("List")?.branches[0].clone();
-            let contents = match tree.branches.len() {
-            	2 => *tree.branches.last().unwrap().clone(),
-            	_ => Node::new(NodeClass::Field, tree.span, vec![Node::new_boxed(NodeClass::Loop("iter".to_string()), tree.span, tree.branches[1..].to_vec())])
-            };
-            let mut comp: Vec<Node> = Vec::new();
-            let mut temp = Ok(Node::void());
-            
-            let loop_scope = env.data[env.depth].clone();
-            for elem in iterator.branches.iter() {
-            	loop_scope.set(index.clone(), *elem.clone())?;
-            	temp = evaluate_scope(env.clone(), loop_scope.clone(), contents.clone());
-                match temp {
-                	Err(ref e) => { 
-						if ErrorClass::Signal("StopIteration") == *e.class { break; }
-						if ErrorClass::Signal("ResetIteration") == *e.class { continue; }
-						else { return temp; }
-                	},
-                	Ok(val) => { comp.push(val); }
-            }}
-            Ok(Node::new(NodeClass::List, tree.span, vec![Node::new(NodeClass::Field, [0, 0], comp)]))
-        },
-        _ => Ok(tree)
+#[cfg(test)]
+mod tests {
+	use std::thread;
+	use super::*;
+	
+	const STACK_SIZE: usize = 256 * 1024 * 1024; // same as the interpreter thread in main
+	
+	// inputs that used to panic, each one has to end in a value or a reported error
+	const CORPUS: [&str; 48] = [
+		"", " ", "\n\n", "$", "}", "{", ")", "(", "]", "[", "()", "[]", "(())", "[[]]",
+		"1 +", "+", "+ 1", "1 + + 2", "2 ++ 3", "1 +\n2 *", "x <-", "<- 1", "a <- }", "x <- 1 +\ny <- 2",
+		"[1, 2 3]", "(4 * 2", "5 )", "{1", "f <- obj(a) { out(a }", "obj", "obj()", "obj(a)", "if 1", "if (True)", "if (x) { 1 }",
+		"loop bad { 1 }", "loop iter (i, [1]) {", "try", "try { 1 } catch", "x:expect", "[1]:[", "\"", "\"abc",
+		"[1]:[3]", "[1]:[-1]", "[1, 2]:[1, 0]", "[1]:[0, 5]", "99999999999999999999999999999999999999999 + 1"
+	];
+	
+	const PROGRAM: &str = "f <- obj(a, b) { if (a > b) { out(a) } { b } }\nl <- [1, 2, 3]:[0, 2]\nx <- try { f(l:[0], 4) } catch (e) { e }\nloop iter (i, l) { x <- x + i }\n";
+	
+	fn survives (source: String) {
+		let shown: String = source.chars().take(60).collect();
+		let run = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || { eval_file(Env::create(), "<corpus>", source, false); }).unwrap();
+		assert!(run.join().is_ok(), "evaluating {:?} panicked", shown);
+	}
+	
+	#[test]
+	fn malformed_input () {
+		for source in CORPUS.iter() { survives(source.to_string()); }
+	}
+	
+	#[test]
+	fn truncated_program () {
+		for end in (0..=PROGRAM.len()).filter(|end| PROGRAM.is_char_boundary(*end)) { survives(PROGRAM[..end].to_string()); }
+	}
+	
+	// nesting past the parser's limit is reported instead of recursing further
+	#[test]
+	fn deep_source () {
+		let depth = 1100; // the limit is 1000 levels
+		survives(format!("{}1{}", "(".repeat(depth), ")".repeat(depth)));
+		survives(format!("x <- {}1{}", "[".repeat(depth), "]".repeat(depth)));
+		survives(format!("x <- {}1{}", "if (True) {".repeat(depth), "}".repeat(depth)));
+		survives(format!("f <- {}1{}", "obj(a) {".repeat(depth), "}".repeat(depth)));
+		survives("(".repeat(depth));
+	}
+	
+	// lists built at runtime have no depth limit, so comparing, writing and dropping them can't recurse
+	// this runs on a default sized thread so a recursive walk would overflow it long before the end
+	#[test]
+	fn deep_runtime_list () {
+		let source = "l <- [0]\nm <- [0]\nc <- 0\nloop cond (c < 100000) { c <- c + 1 l <- [l] m <- [m] 0 }\nx <- l = m\ny <- len(span([0], [1]))\n";
+		let value = thread::spawn(move || eval_file(Env::create(), "<corpus>", source.to_string(), false).map(|value| value.decode())).join().unwrap();
+		assert_eq!(value, Some(String::from("1")));
+		
+		let nest = |inner: f32| (0..100000).fold(Node::number(inner), |list, _| Node::list(vec![list]));
+		let (deep, copy, other) = (nest(0.0), nest(0.0), nest(1.0));
+		thread::spawn(move || {
+			assert!(deep == copy && deep != other);
+			assert!(deep.decode().starts_with("[[[") && deep.show().ends_with(")))"));
+		}).join().unwrap();
+	}
+	
+	// a handler that catches a LimitExceeded only gets the grace allowance, it can't restart the budget by looping
+	#[test]
+	fn caught_limits () {
+		let limits = Limits { steps: Some(100000), nodes: Some(100000), timeout: None };
+		let (sender, receiver) = std::sync::mpsc::channel();
+		thread::spawn(move || {
+			let escape = "n <- 0\nloop cond (True) { try { loop cond (True) { 1 } } catch (e) { n <- n + 1 } }\n";
+			let caught = "try { loop cond (True) { 1 } } catch (e) { e.kind }\n";
+			let _ = sender.send((eval_file(Env::with_limits(limits), "<limits>", escape.to_string(), false).is_none(),
+				eval_file(Env::with_limits(limits), "<limits>", caught.to_string(), false).map(|value| value.decode())));
+		});
+		let (stopped, caught) = receiver.recv_timeout(Duration::from_secs(60)).expect("the step limit didn't stop the program");
+		assert!(stopped);
+		assert_eq!(caught, Some(String::from("LimitExceeded")));
+	}
+	
+	// a directory of module files under the temp dir, named per test so tests running at the same time don't share it
+	fn modules (name: &str, files: &[(&str, &str)]) -> PathBuf {
+		let dir = env::temp_dir().join(format!("kylu-{}-{}", name, process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		for (file, source) in files.iter() { fs::write(dir.join(file), source).unwrap(); }
+		dir
+	}
+	
+	fn run_in (dir: &Path, source: &str) -> Option<String> {
+		let main = dir.join("main.ky");
+		fs::write(&main, source).unwrap();
+		eval_file(Env::create(), &main.to_string_lossy(), source.to_string(), false).map(|value| value.decode())
+	}
+	
+	#[test]
+	fn import_cache () {
+		let dir = modules("cache", &[("m.ky", "v <- 1\n")]);
+		assert_eq!(run_in(&dir, "import \"m\"\nm.v\n"), Some(String::from("1")));
+		// a module is only run once per process, a changed file isn't read again
+		fs::write(dir.join("m.ky"), "v <- 2\n").unwrap();
+		assert_eq!(run_in(&dir, "import \"m.ky\" as k\nk.v\n"), Some(String::from("1")));
+		fs::remove_dir_all(dir).unwrap();
+	}
+	
+	#[test]
+	fn import_cycle () {
+		let dir = modules("cycle", &[("a.ky", "import \"b\"\nx <- 1\n"), ("b.ky", "import \"a\"\n"),
+			("left.ky", "import \"base\"\nx <- base.v\n"), ("right.ky", "import \"base\"\nx <- base.v * 2\n"), ("base.ky", "v <- 5\n")]);
+		assert_eq!(run_in(&dir, "import \"a\"\na.x\n"), None);
+		// two modules importing the same one isn't a cycle
+		assert_eq!(run_in(&dir, "import \"left\"\nimport \"right\"\nleft.x + right.x\n"), Some(String::from("15")));
+		fs::remove_dir_all(dir).unwrap();
+	}
+	
+	#[test]
+	fn exports () {
+		let dir = modules("exports", &[("m.ky", "export (a)\na <- 1\nb <- 2\n"), ("all.ky", "a <- 1\nb <- 2\n")]);
+		assert_eq!(run_in(&dir, "import \"m\" as m\nimport (a) from \"m\"\nm.a + a\n"), Some(String::from("2")));
+		assert_eq!(run_in(&dir, "import \"m\"\nm.b\n"), None);
+		assert_eq!(run_in(&dir, "import (b) from \"m\"\nb\n"), None);
+		// without an export statement every global is public
+		assert_eq!(run_in(&dir, "import (b) from \"all\"\nb\n"), Some(String::from("2")));
+		fs::remove_dir_all(dir).unwrap();
 	}
 }
@@ -14,32 +14,65 @@
 */
 
 mod parser;
+mod compiler;
+mod vm;
 mod evaluator;
 mod utils;
 
-use std::{ env, process, io };
-use std::io::Write;
-use crate::evaluator::{ Env, eval_file, load_file };
-use crate::utils::node::Node;
+use std::{ env, fs, io, process, thread };
+use std::io::Read;
+use rustyline::{ Editor, history::FileHistory, error::ReadlineError };
+use std::time::{ Duration, Instant };
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use crate::evaluator::{ Env, Limits, INTERRUPT, eval_file, load_file, parse_source, import_module, search_path };
+use crate::utils::node::{ NodeClass, Node, intern };
+use crate::utils::error::ErrorFormat;
+use crate::utils::terminal::{ KyluHelper, history_path, help };
+use crate::utils::workspace::{ save_workspace, load_workspace };
 
 
-fn run (file_name: String) {
-	let mut source_file: String = load_file(file_name, true);
-	let env = Env::create();
-	eval_file(env, source_file, true);
+// kylu calls nest on the native stack, so the interpreter gets a thread with room for deep recursion
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+const TERMINAL: &str = "<terminal>"; // file name for code entered at the prompt
+const STDIN: &str = "<stdin>";
+const EXPRESSION: &str = "<expression>";
+
+
+// what to run, the terminal is started if none is given
+enum Program {
+	File(String),
+	Stdin, // kylu -
+	Expression(String) // kylu -e <expr>, the value is printed
+}
+
+
+// arguments after the program are given to it as the global list args
+fn bind_args (env: &Env, args: &[String]) {
+	let args: Vec<Node> = args.iter().map(|arg| Node::string(arg.clone())).collect();
+	let _ = env.data[0].set(Node::symbol("args".to_string()), Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], args)]));
+}
+
+
+fn run (file_name: String, env: Env) {
+	match load_file(&file_name) {
+		Ok(source_file) => { eval_file(env, &file_name, source_file, true); },
+		Err(e) => { e.report(env.error_format, String::new(), &file_name, Vec::new(), true); }
+	}
 }
 
 
-fn get_input(prompt: &str) -> String {
-	let mut command = String::new();
-	print!("{}", prompt);
-	io::stdout().flush().unwrap();
-	io::stdin().read_line(&mut command).unwrap();
-	command
+fn run_stdin (env: Env) {
+	let mut source = String::new();
+	if let Err(err) = io::stdin().read_to_string(&mut source) {
+		eprintln!("[-] an error occurred while reading the program from stdin: {}", err);
+		process::exit(1);
+	}
+	eval_file(env, STDIN, source, true);
 }
 
 
-fn terminal() {
+fn terminal(mut env: Env) {
     println!(
 r"
 ---------------------------------------------------------------------------------
@@ -57,22 +90,53 @@ r"
 ---------------------------------------------------------------------------------
 "
 	);
-	let env = Env::create();
+	let mut editor: Editor<KyluHelper, FileHistory> = Editor::new().unwrap_or_else(|err| {
+		eprintln!("[-] couldn't start the terminal: {}", err);
+		process::exit(1);
+	});
+	// ctrl-c stops the running code instead of the session, installed after the editor since it takes over SIGINT while it exists
+	ctrlc::set_handler(|| INTERRUPT.store(true, Ordering::Relaxed)).unwrap_or_else(|err| eprintln!("[-] couldn't install interrupt handler: {}", err));
+	editor.set_helper(Some(KyluHelper { bindings: env.data[0].clone(), imports: env.import.clone() }));
+	let history = history_path();
+	let _ = editor.load_history(&history); // missing on first run
+	let config = env.clone();
+	let mut session: Vec<String> = Vec::new(); // inputs that evaluated without errors, written by /save
+	
 	loop {
-		let mut command = get_input("(kylu2.3)--> ");
-		if command.chars().nth(0).unwrap() == '/' {
+		let mut command = match editor.readline("(kylu2.3)--> ") {
+			Ok(line) => line,
+			Err(ReadlineError::Interrupted) => { continue; }, // ctrl-c at the prompt discards the line
+			Err(ReadlineError::Eof) => {
+				println!("[-] program stopped");
+				break;
+			},
+			Err(err) => {
+				eprintln!("[-] couldn't read input: {}", err);
+				break;
+		}};
+		if command.trim().is_empty() { continue; }
+		let _ = editor.add_history_entry(command.as_str());
+		
+		if command.starts_with('/') {
 			let mut comm_iter = command.trim().split(" ");
-			match comm_iter.next() {
+			let comm = comm_iter.next();
+			let rest = command.trim()[comm.unwrap_or("").len()..].trim().to_string(); // expression or path after the command
+			match comm {
+				Some("/help") => {
+					println!("------------------------------------------------------------------- HELP --------");
+					println!("{}", help());
+					continue;
+				},
 				Some("/exit") | Some("/x") => {
 					println!("[-] program stopped");
-					process::exit(0);
+					break;
 				},
 				Some("/edit") => {
 					command = String::new();
-					loop {
-						let append = get_input("> ");
-						if append == "/done\n" { break; }
+					while let Ok(append) = editor.readline("> ") {
+						if append.trim() == "/done" { break; }
 						command.push_str(&append);
+						command.push('\n');
 				}},
 				Some("/bindings") | Some("/bind") => {
 					println!("--------------------------------------------------------- LOCAL BINDINGS --------");
@@ -92,36 +156,169 @@ r"
 						},
 						Some(path) => path
 					};
-					let source = load_file(path.to_string(), false);
-					if source == String::new() { continue; }
-					
-					let guest_env = Env::create();
-					eval_file(guest_env.clone(), source, false); // evaluate file contents
-					
-					let file_name = path.split("/").collect::<Vec<&str>>().pop().unwrap(); // get last arg in path
-					let mut label = file_name.split(".").nth(0).unwrap(); // remove extension
-					env.import.set(Node::symbol(label.to_string()), guest_env.data[0].as_node(Node::symbol("<extension>".to_string())));
-
-					println!("[+] loaded file: {}", file_name);					
+					match import_module(&env, path) {
+						Ok(module) => {
+							let label = module.id().0.to_string(); // the file's stem
+							let _ = env.import.set(Node::symbol(label.clone()), module);
+							session.push(format!("import \"{}\"", path));
+							println!("[+] loaded file: {}, members are available as {}.<name>", path, label);
+						},
+						Err(e) => { e.report(env.error_format, String::new(), TERMINAL, Vec::new(), false); }
+					}
+					continue;
+				},
+				Some("/reset") => {
+					env = config.fresh();
+					bind_args(&env, &[]);
+					editor.set_helper(Some(KyluHelper { bindings: env.data[0].clone(), imports: env.import.clone() }));
+					session.clear();
+					println!("[+] environment cleared");
+					continue;
+				},
+				Some("/del") => {
+					match comm_iter.next() {
+						Some(name) if env.data[0].remove(intern(name)) => {
+							session.push(format!("{} <- Void", name)); // scripts can't unbind a name, the replay leaves it empty instead
+							println!("[+] removed binding: {}", name);
+						},
+						Some(name) => { println!("[-] no binding named {}", name); },
+						None => { println!("[-] specify binding to remove"); }
+					}
 					continue;
-				}
+				},
+				Some("/ast") => {
+					let source = format!("{{{}}}$", rest);
+					match parse_source(&source) {
+						Ok(tree) => { println!("{}", tree.show()); },
+						Err(errors) => { for e in errors.iter() { e.report(env.error_format, source.clone(), TERMINAL, Vec::new(), false); } }
+					}
+					continue;
+				},
+				Some("/type") => {
+					if let Some(value) = eval_file(env.clone(), TERMINAL, rest.clone(), false) {
+						println!("{}", value.get_type());
+						session.push(rest);
+					}
+					continue;
+				},
+				Some("/time") => {
+					let start = Instant::now();
+					let result = eval_file(env.clone(), TERMINAL, rest.clone(), false);
+					println!("\n[+] finished in {:?}", start.elapsed());
+					if result.is_some() { session.push(rest); }
+					continue;
+				},
+				Some("/snapshot") => {
+					let result = match (comm_iter.next(), comm_iter.next()) {
+						(Some("save"), Some(path)) => save_workspace(&env, path).map(|_| format!("saved workspace to {}", path)),
+						(Some("load"), Some(path)) => load_workspace(&env, path).map(|_| format!("loaded workspace from {}", path)),
+						_ => {
+							println!("[-] usage: /snapshot save|load <file>");
+							continue;
+					}};
+					match result {
+						Ok(msg) => { println!("[+] {}", msg); },
+						Err(e) => { e.report(env.error_format, String::new(), TERMINAL, Vec::new(), false); }
+					}
+					continue;
+				},
+				Some("/save") => {
+					let path = match comm_iter.next() {
+						Some(path) => path,
+						None => {
+							println!("[-] specify file path to save to");
+							continue;
+					}};
+					match fs::write(path, session.join("\n") + "\n") {
+						Ok(()) => { println!("[+] saved session to {}", path); },
+						Err(err) => { println!("[-] couldn't save session to {}: {}", path, err); }
+					}
+					continue;
+				},
 				Some(inv) => {
 					println!("[-] invalid terminal command: {}", inv);
 					continue;
 				},
 				None => { continue; }
 		}}
-		eval_file(env.clone(), command, false);
-		println!("");
+		if eval_file(env.clone(), TERMINAL, command.clone(), false).is_some() { session.push(command); }
+		println!();
 	}
+	if let Err(err) = editor.save_history(&history) { eprintln!("[-] couldn't save history to {}: {}", history.display(), err); }
+}
+
+
+fn parse_option<T: std::str::FromStr> (option: &str, value: &str) -> T {
+	value.parse::<T>().unwrap_or_else(|_| {
+		eprintln!("[-] invalid value for {}: {}", option, value);
+		process::exit(1);
+	})
 }
 
 
 fn main () {
-    match env::args().nth(1) {
-        Some(file) => run(file),
-        None => terminal()
-	};
+	let mut config = Env::with_limits(Limits::default());
+	let mut program = None;
+	let mut args = env::args().skip(1);
+	// options come before the program, everything after it belongs to the script
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-" => {
+				program = Some(Program::Stdin);
+				break;
+			},
+			"-e" => {
+				match args.next() {
+					Some(expr) => { program = Some(Program::Expression(expr)); },
+					None => {
+						eprintln!("[-] -e requires an expression to evaluate");
+						process::exit(1);
+				}}
+				break;
+			},
+			_ => ()
+		}
+		match arg.split_once("=") {
+			Some((opt @ "--recursion-limit", value)) => { config.call_limit = parse_option(opt, value); },
+			Some((opt @ "--max-steps", value)) => { config.budget.limits.steps = Some(parse_option(opt, value)); },
+			Some((opt @ "--max-nodes", value)) => { config.budget.limits.nodes = Some(parse_option(opt, value)); },
+			Some(("--error-format", "text")) => { config.error_format = ErrorFormat::Text; },
+			Some(("--error-format", "json")) => { config.error_format = ErrorFormat::Json; },
+			Some(("--error-format", value)) => {
+				eprintln!("[-] invalid value for --error-format: {}, expected text or json", value);
+				process::exit(1);
+			},
+			Some((opt @ "--timeout", value)) => { // seconds
+				let timeout = Duration::try_from_secs_f64(parse_option(opt, value)).unwrap_or_else(|_| {
+					eprintln!("[-] invalid value for {}: {}", opt, value);
+					process::exit(1);
+				});
+				config.budget.limits.timeout = Some(timeout);
+			},
+			_ => {
+				program = Some(Program::File(arg));
+				break;
+			}
+	}}
+	let args: Vec<String> = args.collect();
+	bind_args(&config, &args);
+	
+	if let Some(Program::File(ref file)) = program {
+		config.search = Arc::new(search_path(file));
+		if let Ok(script) = fs::canonicalize(file) { config.loading = Arc::new(vec![(script, file.clone())]); } // imports of the script itself are cycles
+	}
+	
+	let interpreter = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+	    match program {
+	        Some(Program::File(file)) => run(file, config),
+	        Some(Program::Stdin) => run_stdin(config),
+	        Some(Program::Expression(expr)) => {
+	        	if let Some(value) = eval_file(config, EXPRESSION, expr, true) { println!("{}", value.decode()); }
+	        },
+	        None => terminal(config)
+		};
+	}).unwrap();
+	if interpreter.join().is_err() { process::exit(1); }
 }
 
 
@@ -8,7 +8,11 @@ fn current_tok<'a> (cfg: ParserConfig<'a>) -> Node { cfg.data.read().unwrap().cu
 
 
 // used for fields that need to have a static length (if condition, loop iterator/index)
-fn field_len<'a> (cfg: ParserConfig<'a>) -> usize { cfg.data.read().unwrap().stack.last().unwrap().branches.len() }
+fn field_len<'a> (cfg: ParserConfig<'a>) -> usize { cfg.data.read().unwrap().stack.last().map(|field| field.branches.len()).unwrap_or(0) }
+
+
+// deepest bracket/ statement nesting accepted, deeper sources would exhaust the stack while parsing or compiling
+const MAX_NESTING: usize = 1000;
 
 
 fn str_to_re(pattern: &str) -> Regex { Regex::new(&format!(r"^(?<token>{})(?<whitespace>[\s]*)", pattern)).unwrap() }
@@ -19,11 +23,14 @@ pub struct ParserData {
 	pub stack: Vec<Node>,
 	current_token: Node,
 	pub index: usize,
+	depth: usize,
+	closers: Vec<String> // closing brackets of the fields being parsed, innermost last
 }
 
 #[derive(Clone)]
 pub struct ParserConfig<'a> {
 	pub data: Arc<RwLock<ParserData>>, // dynamic objects placed in shared reference
+	pub errors: Arc<RwLock<Vec<Err<'a>>>>, // syntax errors the parser recovered from
 	pub source: &'a str,
 	token_patterns: [(regex::Regex, &'a str, Option<u8>); 18]
 }
@@ -35,14 +42,17 @@ impl <'a> ParserConfig<'a> {
 				ParserData {
 					stack: Vec::new(),
 					current_token: Node::new(NodeClass::Misc(String::new()), [0, 0], Vec::new()),
-					index: 0
+					index: 0,
+					depth: 0,
+					closers: Vec::new()
 			})),
+			errors: Arc::new(RwLock::new(Vec::new())),
 			source,
 			token_patterns: [
 				(str_to_re(r"\'[^\']*\'"), "STRING", None),
 				(str_to_re(r#"\"[^\"]*\""#), "STRING", None),
-				(str_to_re(r"(True|False)"), "BOOLEAN", None),
-				(str_to_re(r"(Void)"), "VOID", None),
+				(str_to_re(r"(True|False)\b"), "BOOLEAN", None), // whole words only, names like VoidReference are symbols
+				(str_to_re(r"(Void)\b"), "VOID", None),
 				(str_to_re(r"[a-zA-Z][a-zA-Z0-9_]*"), "SYMBOL", None),
 				(str_to_re(r"\[[!@]\]"), "SYMBOL", None),
 				(str_to_re(r"[0-9]+\.?[0-9]*"), "NUMBER", None),
@@ -71,7 +81,7 @@ impl <'a> ParserConfig<'a> {
 fn get_token<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 	let update_index = cfg.data.read().unwrap().current_token.span[1];
     cfg.data.write().unwrap().index = update_index;
-    if cfg.source.len() < update_index { return Err(Err::parse_err(ErrorClass::EndOfFile)); }
+    if cfg.source.len() < update_index { return Err(Err::parse_err(ErrorClass::EndOfFile, current_tok(cfg.clone()))); }
     
     let mut value: (&str, usize) = ("", 0);
     let mut meta: (&str, Option<u8>) = ("", None);
@@ -87,7 +97,7 @@ fn get_token<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 	}}
     
     match value.0 {
-		"" => { return Err(Err::parse_err(ErrorClass::UnknownToken)); },
+		"" => { return Err(Err::parse_err(ErrorClass::UnknownToken, current_tok(cfg.clone()))); },
 		_ => ()
 	};
     
@@ -101,10 +111,13 @@ fn get_token<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
         "OPER" => {
         	match meta.1 {
         		Some(p) => NodeClass::Oper(value.0.to_string(), p),
-        		None => { panic!("something went wrong"); }
+        		None => { return Err(Err::parse_err(ErrorClass::UnknownToken, current_tok(cfg.clone()))); }
         }},
-        "NUMBER" => NodeClass::Number(value.0.parse::<f32>().unwrap()),
-        "BOOLEAN" => NodeClass::Boolean(value.0.to_lowercase().parse::<bool>().unwrap()),
+        "NUMBER" => match value.0.parse::<f32>() {
+        	Ok(num) => NodeClass::Number(num),
+        	Err(_) => { return Err(Err::parse_err(ErrorClass::UnknownToken, current_tok(cfg.clone()))); }
+        },
+        "BOOLEAN" => NodeClass::Boolean(value.0 == "True"),
         "SYMBOL" => NodeClass::Symbol(value.0.to_string()),
         "VOID" => NodeClass::Void,
         "" => NodeClass::Misc(value.0.to_string()),
@@ -120,18 +133,33 @@ fn get_token<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 
 
 pub fn parse<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
+	{
+		let mut config = cfg.data.write().unwrap();
+		if config.depth >= MAX_NESTING { return Err(Err::parse_err(ErrorClass::ResolutionFailure("EXPRESSION", format!("nested more than {} levels deep", MAX_NESTING)), config.current_token.clone())); }
+		config.depth += 1;
+	}
+	let result = element(cfg.clone());
+	cfg.data.write().unwrap().depth -= 1;
+	result
+}
+
+
+fn element<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 	let init_idx = cfg.data.read().unwrap().index;
 	match current_tok(cfg.clone()).id() {
 		("if", "Symbol") => if_stmnt(cfg.clone())?,
 		("loop", "Symbol") => loop_stmnt(cfg.clone())?,
+		("try", "Symbol") => try_stmnt(cfg.clone())?,
+		("import", "Symbol") => import_stmnt(cfg.clone())?,
+		("export", "Symbol") => export_stmnt(cfg.clone())?,
 		("obj", "Symbol") => object_stmnt(cfg.clone())?,
 		("(", "MiscCharacter") => {
 			field(cfg.clone(), "(", ")", None)?;
-			reduce(cfg.clone(), NodeClass::Paren, 1, init_idx);
+			reduce(cfg.clone(), NodeClass::Paren, 1, init_idx)?;
 		},
 		("[", "MiscCharacter") => {
 			field(cfg.clone(), "[", "]", Some(","))?;
-			reduce(cfg.clone(), NodeClass::List, 1, init_idx);
+			reduce(cfg.clone(), NodeClass::List, 1, init_idx)?;
 		},
 		("{", "MiscCharacter") => field(cfg.clone(), "{", "}", None)?,
 		("-", "Operator") => {
@@ -141,17 +169,42 @@ pub fn parse<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 			cfg.data.write().unwrap().stack.push(Node::new(NodeClass::Number((0.0-num) as f32), num_tok.span, Vec::new()));
 			get_token(cfg.clone())?;
 		},
-		(other, "MiscCharacter") | (other, "Operator") => { return Err(Err::parse_err(ErrorClass::UnknownSyntax(other.to_string()))); }
+		(other, "MiscCharacter") | (other, "Operator") => { return Err(Err::parse_err(ErrorClass::UnknownSyntax(other.to_string()), current_tok(cfg.clone()))); }
 		_ => {
 			let token = current_tok(cfg.clone());
 			cfg.data.write().unwrap().stack.push(token);
 			get_token(cfg.clone())?;
+			member(cfg.clone())?;
 		}
 	}
 	Ok(())
 }
 
 
+// qualified access (module.member), the member is taken from the instance on the left
+fn member<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
+	while (".", "MiscCharacter") == current_tok(cfg.clone()).id() {
+		get_token(cfg.clone())?;
+		let name = current_tok(cfg.clone());
+		if name.get_type() != "Symbol" { return Err(Err::parse_err(ErrorClass::ResolutionFailure("MEMBER", String::from("expected a name after '.'")), name)); }
+		get_token(cfg.clone())?;
+		let mut config = cfg.data.write().unwrap();
+		let object = config.stack.pop().unwrap(); // pushed by element()
+		config.stack.push(Node::new(NodeClass::OperExpr(".".to_string()), [object.span[0], name.span[1]], vec![object, name]));
+	}
+	Ok(())
+}
+
+
+// both sides of a binary operator, inputs like "1 +" leave one of them missing
+fn operands<'a> (stack: &mut Vec<Node>, operator: &Node) -> Result<(Node, Node), Err<'a>> {
+	if stack.len() < 2 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("OPERATOR", format!("'{}' is missing an operand", operator.id().0)), operator.clone())); }
+	let r_op = stack.pop().unwrap();
+	let l_op = stack.pop().unwrap();
+	Ok((l_op, r_op))
+}
+
+
 fn oper_expr<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 	let init_idx = cfg.data.read().unwrap().index;
 	let mut oper_stack: Vec<Node> = Vec::new();
@@ -162,20 +215,19 @@ fn oper_expr<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 			field(cfg.clone(), "(", ")", Some(","))?;
 			if ("(", "MiscCharacter") == current_tok(cfg.clone()).id() {
 				field(cfg.clone(), "(", ")", Some(","))?;
-				reduce(cfg.clone(), NodeClass::Combinator, 3, init_idx);
+				reduce(cfg.clone(), NodeClass::Combinator, 3, init_idx)?;
 			}
-			else { reduce(cfg.clone(), NodeClass::Call, 2, init_idx); }
+			else { reduce(cfg.clone(), NodeClass::Call, 2, init_idx)?; }
 		}
 		{
 			let mut config_writer = cfg.data.write().unwrap();
 			let p_curr = if let NodeClass::Oper(_, priority) = *config_writer.current_token.signature { priority }else { break; };
 			
 			if oper_stack.len() == 0 { oper_stack.push(config_writer.current_token.clone()); }
-			else if let NodeClass::Oper(operator, priority) = *oper_stack.last().unwrap().signature.clone() { 
+			else if let NodeClass::Oper(operator, priority) = (*oper_stack.last().unwrap().signature).clone() { 
 				if p_curr < priority { oper_stack.push(config_writer.current_token.clone()); }
 				if p_curr >= priority {
-					let r_op = config_writer.stack.pop().unwrap();
-					let l_op = config_writer.stack.pop().unwrap();
+					let (l_op, r_op) = operands(&mut config_writer.stack, oper_stack.last().unwrap())?;
 					config_writer.stack.push( Node::new(NodeClass::OperExpr(operator), [l_op.span[0], r_op.span[1]], vec![l_op, r_op]) );
 					oper_stack.pop();
 					oper_stack.push(config_writer.current_token.clone());
@@ -185,79 +237,178 @@ fn oper_expr<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 	{
 		let mut config_writer = cfg.data.write().unwrap();
 		for operator in oper_stack.iter().rev() {
-		    let r_op = config_writer.stack.pop().unwrap();
-		    let l_op = config_writer.stack.pop().unwrap();
-		    let oper = if let NodeClass::Oper(ref op, _) = *operator.signature { op }else { panic!("something went wrong"); };
+		    let oper = operator.id().0;
+		    let (l_op, r_op) = operands(&mut config_writer.stack, operator)?;
 		    config_writer.stack.push( Node::new(NodeClass::OperExpr(oper.to_string()), [l_op.span[0], r_op.span[1]], vec![l_op, r_op]) );
 	}}
 	Ok(())
 }
 
 
-fn reduce<'a> (cfg: ParserConfig<'a>, class: NodeClass, length: usize, init_idx: usize) {
+fn reduce<'a> (cfg: ParserConfig<'a>, class: NodeClass, length: usize, init_idx: usize) -> Result<(), Err<'a>> {
     {
     	let mut config = cfg.data.write().unwrap();
 
-		let offset = config.stack.len() - length;
+		let offset = match config.stack.len().checked_sub(length) {
+			Some(offset) => offset,
+			None => {
+				let name = Node::new(class, [init_idx, config.index], Vec::new()).show();
+				return Err(Err::parse_err(ErrorClass::ResolutionFailure("EXPRESSION", format!("{} is missing elements", name)), config.current_token.clone()));
+		}};
 		let tree: Vec<Node> = config.stack[offset..config.stack.len()].to_vec();
 		config.stack.truncate(offset);
 		let final_idx = config.index;
 		config.stack.push(Node::new(class, [init_idx, final_idx], tree));
 	}
+	Ok(())
 }
 
 
 fn field<'a> (cfg: ParserConfig<'a>, start: &'a str, end: &'a str, delim: Option<&'a str>) -> Result<(), Err<'a>> {
 	if (start, "MiscCharacter") != current_tok(cfg.clone()).id() { return Ok(()); }
 	let init_idx = cfg.data.read().unwrap().index;
+	let opening = current_tok(cfg.clone());
 	let mut length = 0;
 	get_token(cfg.clone())?;
+	let level = {
+		let mut config = cfg.data.write().unwrap();
+		config.closers.push(end.to_string());
+		config.closers.len()
+	};
 
-	while (end, "MiscCharacter") != current_tok(cfg.clone()).id() {
-		oper_expr(cfg.clone())?;
-		length += 1;
+	loop {
+		let token = current_tok(cfg.clone());
+		// the brace added around the source can only close the outermost field
+		let wrapper = token.span[0] + 2 == cfg.source.len();
+		let closing = (end, "MiscCharacter") == token.id();
+		if closing && wrapper == (level == 1) { break; }
+		if at_end(cfg.clone(), &token) || closes_outer(cfg.clone(), &token) || (closing && wrapper) { // leave the closing bracket to the field it belongs to
+			report(cfg.clone(), Err::parse_err(ErrorClass::UnmatchedBracket(start.to_string()), opening));
+			cfg.data.write().unwrap().closers.pop();
+			return reduce(cfg, NodeClass::Field, length, init_idx);
+		}
+		if let (")", "MiscCharacter") | ("]", "MiscCharacter") | ("}", "MiscCharacter") = token.id() { // bracket that was never opened
+			report(cfg.clone(), Err::parse_err(ErrorClass::UnknownSyntax(token.id().0.to_string()), token));
+			get_token(cfg.clone())?;
+			continue;
+		}
 		
-		match delim {
-			None => (),
+		let height = cfg.data.read().unwrap().stack.len();
+		let element = oper_expr(cfg.clone()).and_then(|_| match delim {
 			Some(sep) => {
+				if (sep, "MiscCharacter") == current_tok(cfg.clone()).id() { get_token(cfg.clone()) }
+				// don't expect delim after last element, a missing closing bracket is reported as an unclosed bracket
+				else if (end, "MiscCharacter") == current_tok(cfg.clone()).id() || at_end(cfg.clone(), &current_tok(cfg.clone())) || closes_outer(cfg.clone(), &current_tok(cfg.clone())) { Ok(()) }
+				else {
+					let before = cfg.data.read().unwrap().stack.last().map(|elem| elem.show()).unwrap_or_default();
+					Err(Err::parse_err(ErrorClass::MissingSeperator(sep.to_string(), before), current_tok(cfg.clone())))
+			}},
+			None => Ok(())
+		});
+		length += 1;
+		if let Err(e) = element {
+			// keep going from the next element so every error in the source is reported, the failed element becomes void
+			report(cfg.clone(), e);
+			{
+				let mut config = cfg.data.write().unwrap();
+				config.closers.truncate(level);
+				config.stack.truncate(height);
+				config.stack.push(Node::new(NodeClass::Void, token.span, Vec::new()));
+			}
+			resynchronize(cfg.clone(), delim)?;
+			if let Some(sep) = delim {
 				if (sep, "MiscCharacter") == current_tok(cfg.clone()).id() { get_token(cfg.clone())?; }
-				else if (end, "MiscCharacter") == current_tok(cfg.clone()).id() { break; } // don't expect delim after last element
-				else { return Err(Err::parse_err(ErrorClass::MissingSeperator(sep.to_string(), cfg.data.read().unwrap().stack.last().unwrap().show()))); }
 		}}
 	}
+	cfg.data.write().unwrap().closers.pop();
 	get_token(cfg.clone())?;
-	reduce(cfg, NodeClass::Field, length, init_idx);
+	reduce(cfg, NodeClass::Field, length, init_idx)?;
 	Ok(())
 }
 
 
+// a token can fail more than one check while the parser recovers, it's only reported once
+fn report<'a> (cfg: ParserConfig<'a>, err: Err<'a>) {
+	let mut errors = cfg.errors.write().unwrap();
+	if errors.last().map(|last| last.cause.span) != Some(err.cause.span) { errors.push(err); }
+}
+
+
+// the source is wrapped as {...}$ so reaching the $ means every bracket should have been closed
+fn at_end<'a> (cfg: ParserConfig<'a>, token: &Node) -> bool { token.span[0] + 1 >= cfg.source.len() }
+
+
+// closing bracket for a field further out, the current field was never closed
+fn closes_outer<'a> (cfg: ParserConfig<'a>, token: &Node) -> bool {
+	let config = cfg.data.read().unwrap();
+	match token.id() {
+		(bracket, "MiscCharacter") => config.closers.iter().rev().skip(1).any(|closer| closer == bracket),
+		_ => false
+	}
+}
+
+
+// skip to where the next element could start: a delimiter or closing bracket of the current field,
+// or for fields without delimiters the first token on a new line
+fn resynchronize<'a> (cfg: ParserConfig<'a>, delim: Option<&'a str>) -> Result<(), Err<'a>> {
+	let start = current_tok(cfg.clone()).span[0];
+	let mut depth = 0;
+	loop {
+		let token = current_tok(cfg.clone());
+		if at_end(cfg.clone(), &token) { return Ok(()); }
+		let new_line = token.span[0] > start && cfg.source[..token.span[0]].trim_end_matches(|c: char| c != '\n' && c.is_whitespace()).ends_with('\n');
+		match token.id() {
+			("(", "MiscCharacter") | ("[", "MiscCharacter") | ("{", "MiscCharacter") => { depth += 1; },
+			(")", "MiscCharacter") | ("]", "MiscCharacter") | ("}", "MiscCharacter") => {
+				if depth == 0 { return Ok(()); }
+				depth -= 1;
+			},
+			(sep, "MiscCharacter") if depth == 0 && Some(sep) == delim => { return Ok(()); },
+			_ if depth == 0 && delim.is_none() && new_line => { return Ok(()); },
+			_ => ()
+		}
+		get_token(cfg.clone())?;
+	}
+}
+
+
+// fields that a statement can't do without, field() quietly skips missing ones
+fn block<'a> (cfg: ParserConfig<'a>, start: &'a str, end: &'a str, delim: Option<&'a str>) -> Result<(), Err<'a>> {
+	if (start, "MiscCharacter") != current_tok(cfg.clone()).id() {
+		let before = cfg.data.read().unwrap().stack.last().map(|elem| elem.show()).unwrap_or_default();
+		return Err(Err::parse_err(ErrorClass::MissingSeperator(start.to_string(), before), current_tok(cfg.clone())));
+	}
+	field(cfg, start, end, delim)
+}
+
+
 fn if_stmnt<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
 	let init_idx = cfg.data.read().unwrap().index;
 	let mut length = 0;
 	
 	while ("if", "Symbol") == current_tok(cfg.clone()).id() {
 		get_token(cfg.clone())?;
-		field(cfg.clone(), "(", ")", None)?;
-		if field_len(cfg.clone()) != 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IF", String::from("one boolean condition required for each if statement")))); }
+		block(cfg.clone(), "(", ")", None)?;
+		if field_len(cfg.clone()) != 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IF", String::from("one boolean condition required for each if statement")), current_tok(cfg.clone()))); }
 		
-		field(cfg.clone(), "{", "}", None)?; // action if true
-		if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IF", String::from("action if true block cannot be empty")))); }
+		block(cfg.clone(), "{", "}", None)?; // action if true
+		if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IF", String::from("action if true block cannot be empty")), current_tok(cfg.clone()))); }
         
         match current_tok(cfg.clone()).id() {
             (",", "MiscCharacter") => {
                 get_token(cfg.clone())?;
-                reduce(cfg.clone(), NodeClass::Field, 2, init_idx)
+                reduce(cfg.clone(), NodeClass::Field, 2, init_idx)?
             },
             ("{", "MiscCharacter") => {
                 field(cfg.clone(), "{", "}", None)?;  // action if false (opt)
-                if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IF", String::from("action if false block cannot be empty")))); }
-                reduce(cfg.clone(), NodeClass::Field, 3, init_idx)
+                if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IF", String::from("action if false block cannot be empty")), current_tok(cfg.clone()))); }
+                reduce(cfg.clone(), NodeClass::Field, 3, init_idx)?
             },
-            _ => reduce(cfg.clone(), NodeClass::Field, 2, init_idx)
+            _ => reduce(cfg.clone(), NodeClass::Field, 2, init_idx)?
     	}
     	length += 1;
     }
-    reduce(cfg, NodeClass::If, length, init_idx);
+    reduce(cfg, NodeClass::If, length, init_idx)?;
     Ok(())
 }
 
@@ -269,42 +420,161 @@ fn loop_stmnt<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
     match current_tok(cfg.clone()).id() {
         ("cond", "Symbol") => {
             get_token(cfg.clone())?;
-            field(cfg.clone(), "(", ")", None)?; // condition
-            if field_len(cfg.clone()) != 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("one boolean condition required")))); }
+            block(cfg.clone(), "(", ")", None)?; // condition
+            if field_len(cfg.clone()) != 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("one boolean condition required")), current_tok(cfg.clone()))); }
             
-            field(cfg.clone(), "{", "}", None)?; // loop action
-            if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("loop action cannot be empty")))); }
-            reduce(cfg.clone(), NodeClass::Loop("cond".to_string()), 2, init_idx)
+            block(cfg.clone(), "{", "}", None)?; // loop action
+            if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("loop action cannot be empty")), current_tok(cfg.clone()))); }
+            reduce(cfg.clone(), NodeClass::Loop("cond".to_string()), 2, init_idx)?
         },
         ("iter", "Symbol") => {
         	get_token(cfg.clone())?;
             let mut length = 0;
             while ("(", "MiscCharacter") == current_tok(cfg.clone()).id() {
                 field(cfg.clone(), "(", ")", Some(","))?; // iterator/ index pairs
-                if field_len(cfg.clone()) != 2 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("index/ iterator pair required")))); }
+                if field_len(cfg.clone()) != 2 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("index/ iterator pair required")), current_tok(cfg.clone()))); }
                 length += 1;
             }
-            field(cfg.clone(), "{", "}", None)?; // loop action
-            if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("loop action cannot be empty")))); }
+            block(cfg.clone(), "{", "}", None)?; // loop action
+            if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", String::from("loop action cannot be empty")), current_tok(cfg.clone()))); }
             length += 1;
-            reduce(cfg.clone(), NodeClass::Loop("iter".to_string()), length, init_idx)
+            reduce(cfg.clone(), NodeClass::Loop("iter".to_string()), length, init_idx)?
         },
         _ => {
-        	let bad_loop_type = cfg.data.read().unwrap().stack.last().unwrap().show();
-            return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", format!("invalid loop type: {}", bad_loop_type))));
+        	let bad_loop_type = current_tok(cfg.clone()).show();
+            return Err(Err::parse_err(ErrorClass::ResolutionFailure("LOOP", format!("invalid loop type: {}", bad_loop_type)), current_tok(cfg.clone())));
 	}}
 	Ok(())
 }
 
 
+// try { body } catch (name, kinds...) { handler } ... finally { cleanup }
+// catch clauses reduce to a field of (binding and kinds, handler), the finally clause to a field of (cleanup)
+fn try_stmnt<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
+    let init_idx = cfg.data.read().unwrap().index;
+    get_token(cfg.clone())?;
+    block(cfg.clone(), "{", "}", None)?; // body
+    let mut length = 1;
+    
+    while ("catch", "Symbol") == current_tok(cfg.clone()).id() {
+    	let clause_idx = cfg.data.read().unwrap().index;
+    	get_token(cfg.clone())?;
+    	block(cfg.clone(), "(", ")", Some(","))?; // binding, then the kinds it accepts (any error if there are none)
+    	if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("TRY", String::from("catch clause requires a name for the error")), current_tok(cfg.clone()))); }
+    	block(cfg.clone(), "{", "}", None)?; // handler
+    	reduce(cfg.clone(), NodeClass::Field, 2, clause_idx)?;
+    	length += 1;
+    }
+    if ("finally", "Symbol") == current_tok(cfg.clone()).id() {
+    	let clause_idx = cfg.data.read().unwrap().index;
+    	get_token(cfg.clone())?;
+    	block(cfg.clone(), "{", "}", None)?; // cleanup
+    	reduce(cfg.clone(), NodeClass::Field, 1, clause_idx)?;
+    	length += 1;
+    }
+    if length == 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("TRY", String::from("catch or finally clause required")), current_tok(cfg.clone()))); }
+    
+    reduce(cfg, NodeClass::Try, length, init_idx)?;
+    Ok(())
+}
+
+
+// import "path" as name, import "path" (bound to the file's stem) or import (names...) from "path"
+fn import_stmnt<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
+    let init_idx = cfg.data.read().unwrap().index;
+    get_token(cfg.clone())?;
+    
+    let selective = ("(", "MiscCharacter") == current_tok(cfg.clone()).id();
+    if selective {
+    	field(cfg.clone(), "(", ")", Some(","))?; // names to bind in the current scope
+    	if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IMPORT", String::from("at least one name required")), current_tok(cfg.clone()))); }
+    	if ("from", "Symbol") != current_tok(cfg.clone()).id() {
+    		let before = cfg.data.read().unwrap().stack.last().map(|elem| elem.show()).unwrap_or_default();
+    		return Err(Err::parse_err(ErrorClass::MissingSeperator("from".to_string(), before), current_tok(cfg.clone())));
+    	}
+    	get_token(cfg.clone())?;
+    }
+    
+    let path = current_tok(cfg.clone());
+    if path.get_type() != "String" { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IMPORT", String::from("expected a file path")), path)); }
+    get_token(cfg.clone())?;
+    
+    let alias = match current_tok(cfg.clone()).id() {
+    	("as", "Symbol") if !selective => {
+    		get_token(cfg.clone())?;
+    		let alias = current_tok(cfg.clone());
+    		if alias.get_type() != "Symbol" { return Err(Err::parse_err(ErrorClass::ResolutionFailure("IMPORT", String::from("expected a name after 'as'")), alias)); }
+    		get_token(cfg.clone())?;
+    		alias
+    	},
+    	_ => Node::void()
+    };
+    {
+    	let mut config = cfg.data.write().unwrap();
+    	let names = match selective {
+    		true => config.stack.pop().unwrap(),
+    		false => Node::new(NodeClass::Field, [0, 0], Vec::new())
+    	};
+    	config.stack.push(path);
+    	config.stack.push(alias);
+    	config.stack.push(names);
+    }
+    reduce(cfg, NodeClass::Import, 3, init_idx)?;
+    Ok(())
+}
+
+
+// export (names...), a module that exports anything keeps every other binding to itself
+fn export_stmnt<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
+    let init_idx = cfg.data.read().unwrap().index;
+    if cfg.data.read().unwrap().closers.len() != 1 { // only the field eval_file wraps the source in
+    	return Err(Err::parse_err(ErrorClass::ResolutionFailure("EXPORT", String::from("exports are only allowed at the top level of a file")), current_tok(cfg.clone())));
+    }
+    get_token(cfg.clone())?;
+    block(cfg.clone(), "(", ")", Some(","))?;
+    if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("EXPORT", String::from("at least one name required")), current_tok(cfg.clone()))); }
+    reduce(cfg, NodeClass::Export, 1, init_idx)?;
+    Ok(())
+}
+
+
 fn object_stmnt<'a> (cfg: ParserConfig<'a>) -> Result<(), Err<'a>> {
     let init_idx = cfg.data.read().unwrap().index;
     get_token(cfg.clone())?;
-    field(cfg.clone(), "(", ")", Some(","))?; // args
-    field(cfg.clone(), "{", "}", None)?; // contents
-    if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("OBJECT", String::from("object contents cannot be empty")))); }
+    block(cfg.clone(), "(", ")", Some(","))?; // args
+    block(cfg.clone(), "{", "}", None)?; // contents
+    if field_len(cfg.clone()) < 1 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("OBJECT", String::from("object contents cannot be empty")), current_tok(cfg.clone()))); }
+    {
+    	let mut config = cfg.data.write().unwrap();
+    	if config.stack.len() < 2 { return Err(Err::parse_err(ErrorClass::ResolutionFailure("OBJECT", String::from("arguments and contents required")), config.current_token.clone())); }
+    	let mut contents = config.stack.pop().unwrap();
+    	resolve_locals(&mut contents, config.stack.last().unwrap());
+    	config.stack.push(contents);
+    }
 
-    reduce(cfg, NodeClass::Object, 2, init_idx);
+    reduce(cfg, NodeClass::Object, 2, init_idx)?;
     Ok(())
 }
 
+
+// arguments always fill the first slots of a function's namespace, so references to them
+// can be resolved here instead of being looked up by name every time they are used
+fn resolve_locals (tree: &mut Node, args: &Node) {
+	if let NodeClass::Symbol(ref name) = *tree.signature {
+		if let Some(slot) = args.branches.iter().position(|arg| arg.id() == (name.as_str(), "Symbol")) {
+			tree.signature = Arc::new(NodeClass::Local(name.to_string(), slot));
+		}
+		return;
+	}
+	match tree.id() {
+		("_", "Object") => { return; }, // nested objects get their own namespace
+		(":", "OperatorExpression") | (".", "OperatorExpression") => { // rhs may run inside an object instance's namespace
+			resolve_locals(&mut tree.branches_mut()[0], args);
+			return;
+		},
+		("_", "ImportStatement") => { return; }, // names are looked up in the module
+		_ => ()
+	}
+	for branch in tree.branches_mut().iter_mut() { resolve_locals(branch, args); }
+}
+
@@ -1,33 +1,57 @@
 /*
 functions included here:
 
--------- vector/ matrix operations
-product
-dot_product
-transpose
-determinant
-adjoint
-invert
+-------- lists
 span
-
--------- set operations
 intersect
-different
-contains
-maximum
-minimum
+len
+in
 
 -------- type conversion
-to_int
-to_string
+toNumber
+
+-------- process information
+getenv
+setenv
+cwd
+now
+clock
+platform
+
+-------- files
+read_text
+write_text
+append_text
+read_lines
+exists
+list_dir
+mkdir
+remove
+read_csv
+write_csv
+
+-------- data formats
+from_json
+to_json
+load_npy
+save_npy
+load_npz
+save_npz
+read_fits
+write_fits
+
+-------- workspaces
+save_workspace
+load_workspace
 
 -------- core utils
+raise
+exit
 write
 reset
 stop
 out
 type
-ext
 prompt
 
 */
@@ -37,102 +61,115 @@ prompt
 use regex::Regex; // used for bool and int type conversion
 use std::io::Write; // used by prompt and write functions
 use std::io;
+use std::{ env, fs };
+use std::path::Path;
+use std::sync::{ OnceLock, RwLock };
+use std::time::{ Instant, SystemTime, UNIX_EPOCH };
 use crate::utils::node::{ NodeClass, Node };
 use crate::utils::error::{ ErrorClass, Err };
-use crate::evaluator::{ Env, evaluate, load_file };
+use crate::evaluator::{ Env, NameSpace };
+use crate::utils::workspace::{ save_workspace, load_workspace };
+use crate::utils::{ csv, json, npy, fits };
 
 
-fn intersect<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
+fn intersect<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
 	let a = tree.branches[0].validate_type("List")?.branches[0].branches.clone();
 	let b = tree.branches[1].validate_type("List")?.branches[0].branches.clone();
 	let mut similar = Vec::new();
-	for elem_a in a.into_iter() {
-		match b.iter().find(|elem_b| elem_a == **elem_b) {
-			Some(num) => { similar.push(num.clone()); },
-			None => ()
-	}}
-	let r = Node::new(NodeClass::List, [0, 0], vec![Node::new_boxed(NodeClass::Field, [0, 0], similar)]);
+	for elem_a in a.iter() {
+		if let Some(num) = b.iter().find(|elem_b| *elem_a == **elem_b) { similar.push(num.clone()); }
+	}
+	let r = Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], similar)]);
 	return Ok(r);
 }
 
 
-fn length<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
+fn length<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
 	let len = tree.branches[0].validate_type("List")?.branches[0].branches.len();
 	return Ok(Node::number(len as f32));
 }
 
 
-fn contains<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
+fn contains<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
 	let search = tree.branches[0].clone();
 	let target = tree.branches[1].validate_type("List")?.branches[0].branches.clone();
 	
-	match target.into_iter().find(|s| search == *s) {
+	match target.iter().find(|s| search == **s) {
 		Some(_) => { return Ok(Node::boolean(true)); },
 		None => { return Ok(Node::boolean(false)); }
 	}
 }
 
 
-fn span<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
+fn span<'a> (env: &Env, tree: Node) -> Result<Node, Err<'a>> {
 	// get first element from each array
 	let arr1 = tree.branches[0].validate_type("List")?.branches[0].clone();
 	let arr2 = tree.branches[1].validate_type("List")?.branches[0].clone();
 	
 	if arr1.branches.len() != arr2.branches.len() { return Err(Err::new(ErrorClass::IndexError(arr2.branches.len() as f32, arr1.decode()), tree)); }
+	if arr1.branches.is_empty() { return Err(Err::new(ErrorClass::IndexError(0.0, "[]".to_string()), tree)); }
 	let (x1, x2) = (arr1.branches[0].into_number()? as usize, arr2.branches[0].into_number()? as usize);
+	env.budget.allocate(x2.saturating_sub(x1))?;
 	let range_x: Vec<Node> = (x1..x2).map(|val| Node::number(val as f32)).collect();
 	
 	// base case
 	if arr1.branches.len() == 1 { return Ok(Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], range_x)])); }
 	
-	let inner1 = Node::new(NodeClass::List, [0, 0], vec![Node::new_boxed(NodeClass::Field, [0, 0], tree.branches[0].branches[0].branches[1..].to_vec())]);
-	let inner2 = Node::new(NodeClass::List, [0, 0], vec![Node::new_boxed(NodeClass::Field, [0, 0], tree.branches[1].branches[0].branches[1..].to_vec())]);
+	let inner1 = Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], tree.branches[0].branches[0].branches[1..].to_vec())]);
+	let inner2 = Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], tree.branches[1].branches[0].branches[1..].to_vec())]);
 	
 	// first layer
-	let range_y = span(env.clone(), Node::new(NodeClass::Field, [0, 0], vec![inner1, inner2]))?;
+	let range_y = span(env, Node::new(NodeClass::Field, [0, 0], vec![inner1, inner2]))?;
 	
 	let mut result = Vec::new();
 	for x in range_x.into_iter() {
-		for y in range_y.branches[0].branches.clone().into_iter() {
-			let mut elem = vec![Box::new(x.clone())];
-			if ("_", "List") == y.id() { elem.extend(y.branches[0].branches.clone()); }
-			else { elem.push(y); }
-			result.push(Node::new(NodeClass::List, [0, 0], vec![Node::new_boxed(NodeClass::Field, [0, 0], elem)]));
+		for y in range_y.branches[0].branches.iter() {
+			let mut elem = vec![x.clone()];
+			if ("_", "List") == y.id() { elem.extend(y.branches[0].branches.iter().cloned()); }
+			else { elem.push(y.clone()); }
+			result.push(Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], elem)]));
 	}}
 	
 	return Ok(Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], result)]));
 }
 
 
-fn to_num<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
-	let target = *tree.branches[0].clone();
+static NUMBER: OnceLock<Regex> = OnceLock::new();
+
+// the strings toNumber accepts, also used to find numeric csv columns
+pub fn parse_number (text: &str) -> Option<f32> {
+	let pattern = NUMBER.get_or_init(|| Regex::new(r"^\-?[0-9]+\.?[0-9]*$").unwrap()); // simple regex for recognizing floating points
+	if pattern.is_match(text) { text.parse::<f32>().ok() }else { None }
+}
+
+
+fn to_num<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let target = tree.branches[0].clone();
 	match *target.signature {
-		NodeClass::Number(num) => Ok(target.clone()),
+		NodeClass::Number(_) => Ok(target.clone()),
 		NodeClass::Boolean(b) => if b { Ok(Node::number(1.0)) }else { Ok(Node::number(0.0)) },
-		NodeClass::String(ref val) => {
-			let pattern = Regex::new(r"^\-?[0-9]+\.?[0-9]*$").unwrap(); // simple regex for recognizing floating points
-			match pattern.find(val) {
-				Some(num) => Ok(Node::number(num.as_str().parse::<f32>().unwrap())),
-				None => Err(Err::new(ErrorClass::Conversion(format!("{}", val), "String", "Number"), tree))
-		}},
+		NodeClass::String(ref val) => match parse_number(val) {
+			Some(num) => Ok(Node::number(num)),
+			None => Err(Err::new(ErrorClass::Conversion(val.clone(), "String", "Number"), tree))
+		},
 		_ => Err(Err::new(ErrorClass::Conversion(target.decode(), target.get_type(), "Number"), tree))
 	}
 }
 
 
-fn reset<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> { return Err(Err::new(ErrorClass::Signal("ResetIteration"), tree)); }
+fn reset<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> { Err(Err::new(ErrorClass::Signal("ResetIteration"), tree)) }
 
 
-fn stop<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> { return  Err(Err::new(ErrorClass::Signal("StopIteration"), *tree.branches[0].clone())); }
+fn stop<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> { Err(Err::new(ErrorClass::Signal("StopIteration"), tree.branches[0].clone())) }
 
 
-fn out<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> { return Err(Err::new(ErrorClass::Signal("StopFunction"), *tree.branches[0].clone())); }
+fn out<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> { Err(Err::new(ErrorClass::Signal("StopFunction"), tree.branches[0].clone())) }
 
 
-fn class<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> { return Ok(Node::new(NodeClass::String(tree.branches[0].get_type().to_string()), [0, 0], Vec::new())); }
+fn class<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> { Ok(Node::new(NodeClass::String(tree.branches[0].get_type().to_string()), [0, 0], Vec::new())) }
 
 
-fn write<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
+fn write<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
     for string in tree.branches.iter() { 
     	match string.id() {
     		("$n", "String") => { print!("\n"); },
@@ -144,7 +181,7 @@ fn write<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
 }
 
 
-fn prompt<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
+fn prompt<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
 	let prompt = tree.branches[0].into_string()?;
 	let mut command = String::new();
 	print!("{}", prompt);
@@ -154,23 +191,294 @@ fn prompt<'a> (env: Env, tree: Node) -> Result<Node, Err<'a>> {
 }
 
 
+// raise(kind, message, data) with the message and data optional, or raise(error) to re-raise a caught error
+fn raise<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.is_empty() || tree.branches.len() > 3 { return Err(Err::new(ErrorClass::ArgMismatch(3, tree.branches.len()), tree)); }
+	if tree.branches.len() == 1 && tree.branches[0].get_type() == "Error" {
+		let error = NameSpace::from(tree.branches[0].branches[0].clone(), tree.branches[0].branches[1].clone())?;
+		let kind = error.get(Node::symbol("kind".to_string()))?.into_string()?;
+		let message = error.get(Node::symbol("message".to_string()))?.into_string()?;
+		return Err(Err::new(ErrorClass::Raised(kind, message), error.get(Node::symbol("cause".to_string()))?));
+	}
+	let kind = tree.branches[0].into_string()?;
+	let message = match tree.branches.get(1) {
+		Some(message) => message.into_string()?,
+		None => String::new()
+	};
+	let data = tree.branches.get(2).cloned().unwrap_or_else(Node::void);
+	Err(Err::new(ErrorClass::Raised(kind, message), data))
+}
+
+
+// stops the program with the given status (0 if there isn't one), finally blocks still run on the way out
+fn exit<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.len() > 1 { return Err(Err::new(ErrorClass::ArgMismatch(1, tree.branches.len()), tree)); }
+	let code = match tree.branches.first() {
+		Some(code) => code.into_number()?,
+		None => 0.0
+	};
+	if code.fract() != 0.0 || code < i32::MIN as f32 || code > i32::MAX as f32 { return Err(Err::new(ErrorClass::Conversion(code.to_string(), "Number", "Integer"), tree)); }
+	Err(Err::new(ErrorClass::Signal("Exit"), Node::number(code)))
+}
+
+
+// variables changed by setenv, None for removed ones. the real environment is left alone since changing it isn't sound
+// while other threads (the interrupt handler) are running
+static OVERRIDES: RwLock<Vec<(String, Option<String>)>> = RwLock::new(Vec::new());
+
+
+// getenv(name, default), the default is optional and Void if it isn't given
+fn getenv<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.is_empty() || tree.branches.len() > 2 { return Err(Err::new(ErrorClass::ArgMismatch(2, tree.branches.len()), tree)); }
+	let name = tree.branches[0].into_string()?;
+	let value = match OVERRIDES.read().unwrap().iter().find(|(set, _)| *set == name) {
+		Some((_, value)) => value.clone(),
+		None => env::var(&name).ok() // unset or not unicode
+	};
+	match value {
+		Some(value) => Ok(Node::string(value)),
+		None => Ok(tree.branches.get(1).cloned().unwrap_or_else(Node::void))
+	}
+}
+
+
+// setenv(name, value) changes the variable for later getenv calls in this process, a Void value removes it
+fn setenv<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let name = tree.branches[0].into_string()?;
+	if name.is_empty() || name.contains('=') || name.contains('\0') {
+		return Err(Err::new(ErrorClass::CustomError(format!("'{}' is not a valid environment variable name", name)), tree.branches[0].clone()));
+	}
+	let value = match tree.branches[1].get_type() {
+		"Void" => None,
+		_ => Some(tree.branches[1].into_string()?)
+	};
+	if value.as_ref().is_some_and(|value| value.contains('\0')) { return Err(Err::new(ErrorClass::CustomError(String::from("environment variables cannot contain null characters")), tree.branches[1].clone())); }
+	let mut overrides = OVERRIDES.write().unwrap();
+	match overrides.iter_mut().find(|(set, _)| *set == name) {
+		Some(entry) => { entry.1 = value.clone(); },
+		None => { overrides.push((name, value.clone())); }
+	}
+	Ok(value.map(Node::string).unwrap_or_else(Node::void))
+}
+
+
+fn cwd<'a> (_env: &Env, _tree: Node) -> Result<Node, Err<'a>> {
+	match env::current_dir() {
+		Ok(dir) => Ok(Node::string(dir.to_string_lossy().to_string())),
+		Err(_) => Ok(Node::void()) // removed while we were in it
+	}
+}
+
+
+// days since 1970-01-01 to a (year, month, day) date, from Howard Hinnant's civil_from_days
+fn civil_date (days: i64) -> (i64, i64, i64) {
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 }else { mp - 9 };
+	(yoe + era * 400 + if month <= 2 { 1 }else { 0 }, month, day)
+}
+
+
+// the utc date and time as [year, month, day, hour, minute, second], numbers are f32 so a timestamp would lose minutes
+fn now<'a> (_env: &Env, _tree: Node) -> Result<Node, Err<'a>> {
+	let since_epoch = match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(since_epoch) => since_epoch,
+		Err(_) => { return Ok(Node::void()); } // clock set before 1970
+	};
+	let secs = since_epoch.as_secs() as i64;
+	let (year, month, day) = civil_date(secs.div_euclid(86400));
+	let time = secs.rem_euclid(86400);
+	let second = (time % 60) as f32 + since_epoch.subsec_millis() as f32 / 1000.0;
+	let fields = vec![year as f32, month as f32, day as f32, (time / 3600) as f32, (time % 3600 / 60) as f32, second];
+	Ok(Node::list(fields.into_iter().map(Node::number).collect()))
+}
+
+
+static CLOCK_START: OnceLock<Instant> = OnceLock::new();
+
+// monotonic seconds since clock() was first called, subtract two readings to time code. the value is an f32 so it
+// only resolves milliseconds for the first two hours or so (8192 seconds), after that the steps grow with the reading
+fn clock<'a> (_env: &Env, _tree: Node) -> Result<Node, Err<'a>> {
+	let start = CLOCK_START.get_or_init(Instant::now);
+	Ok(Node::number(start.elapsed().as_secs_f32()))
+}
+
+
+fn platform<'a> (_env: &Env, _tree: Node) -> Result<Node, Err<'a>> { Ok(Node::string(env::consts::OS.to_string())) }
+
+
+fn read_text<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let path = &tree.branches[0];
+	match fs::read_to_string(path.into_string()?) {
+		Ok(text) => Ok(Node::string(text)),
+		Err(err) => Err(Err::file(format!("could not be read: {}", err), path))
+	}
+}
+
+
+// read_text split into lines without their line endings
+fn read_lines<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let path = &tree.branches[0];
+	match fs::read_to_string(path.into_string()?) {
+		Ok(text) => Ok(Node::list(text.lines().map(|line| Node::string(line.to_string())).collect())),
+		Err(err) => Err(Err::file(format!("could not be read: {}", err), path))
+	}
+}
+
+
+// replaces the file's contents, creating it if needed
+fn write_text<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let path = &tree.branches[0];
+	match fs::write(path.into_string()?, tree.branches[1].into_string()?) {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be written: {}", err), path))
+	}
+}
+
+
+fn append_text<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let path = &tree.branches[0];
+	let text = tree.branches[1].into_string()?;
+	let appended = fs::OpenOptions::new().create(true).append(true).open(path.into_string()?).and_then(|mut file| file.write_all(text.as_bytes()));
+	match appended {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be appended to: {}", err), path))
+	}
+}
+
+
+fn exists<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> { return Ok(Node::boolean(Path::new(&tree.branches[0].into_string()?).exists())); }
+
+
+// entry names in the directory, sorted since the os gives them in no particular order
+fn list_dir<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let path = &tree.branches[0];
+	let entries = fs::read_dir(path.into_string()?).and_then(|dir| dir.map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().to_string())).collect::<Result<Vec<String>, io::Error>>());
+	match entries {
+		Ok(mut names) => {
+			names.sort();
+			Ok(Node::list(names.into_iter().map(Node::string).collect()))
+		},
+		Err(err) => Err(Err::file(format!("could not be listed: {}", err), path))
+	}
+}
+
+
+// creates missing parent directories too, an existing directory isn't an error
+fn mkdir<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let path = &tree.branches[0];
+	match fs::create_dir_all(path.into_string()?) {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be created: {}", err), path))
+	}
+}
+
+
+// removes a file or an empty directory, never a directory's contents
+fn remove<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	let path = &tree.branches[0];
+	let name = path.into_string()?;
+	let removed = match fs::symlink_metadata(&name) {
+		Ok(meta) if meta.is_dir() => fs::remove_dir(&name),
+		_ => fs::remove_file(&name)
+	};
+	match removed {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be removed: {}", err), path))
+	}
+}
+
+
+// read_csv(path, options), see utils/csv.rs for the options
+fn read_csv<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.is_empty() || tree.branches.len() > 2 { return Err(Err::new(ErrorClass::ArgMismatch(2, tree.branches.len()), tree)); }
+	csv::read_csv(&tree.branches[0], tree.branches.get(1))
+}
+
+
+// write_csv(path, rows, options)
+fn write_csv<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.len() < 2 || tree.branches.len() > 3 { return Err(Err::new(ErrorClass::ArgMismatch(3, tree.branches.len()), tree)); }
+	csv::write_csv(&tree.branches[0], &tree.branches[1], tree.branches.get(2))
+}
+
+
+fn from_json<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> { json::from_json(&tree.branches[0].validate_type("String")?.into_string()?) }
+
+
+// to_json(value, pretty), pretty printing is off unless given
+fn to_json<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.is_empty() || tree.branches.len() > 2 { return Err(Err::new(ErrorClass::ArgMismatch(2, tree.branches.len()), tree)); }
+	let pretty = match tree.branches.get(1) {
+		Some(pretty) => pretty.into_boolean()?,
+		None => false
+	};
+	json::to_json(&tree.branches[0], pretty)
+}
+
+
+// save_npy(path, array, dtype), the dtype defaults to float64 (or bool for an array of booleans)
+fn save_npy<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.len() < 2 || tree.branches.len() > 3 { return Err(Err::new(ErrorClass::ArgMismatch(3, tree.branches.len()), tree)); }
+	npy::save_npy(&tree.branches[0], &tree.branches[1], tree.branches.get(2))
+}
+
+
+// save_npz(path, arrays, dtype), arrays is an object instance of named arrays or a list
+fn save_npz<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.len() < 2 || tree.branches.len() > 3 { return Err(Err::new(ErrorClass::ArgMismatch(3, tree.branches.len()), tree)); }
+	npy::save_npz(&tree.branches[0], &tree.branches[1], tree.branches.get(2))
+}
+
+
+// write_fits(path, image, header), or write_fits(path, hdus) with the list read_fits returns
+fn write_fits<'a> (_env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	if tree.branches.len() < 2 || tree.branches.len() > 3 { return Err(Err::new(ErrorClass::ArgMismatch(3, tree.branches.len()), tree)); }
+	fits::write_fits(&tree.branches[0], &tree.branches[1], tree.branches.get(2))
+}
+
+
+fn save<'a> (env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	save_workspace(env, &tree.branches[0].into_string()?)?;
+	Ok(Node::void())
+}
+
+
+fn load<'a> (env: &Env, tree: Node) -> Result<Node, Err<'a>> {
+	load_workspace(env, &tree.branches[0].into_string()?)?;
+	Ok(Node::void())
+}
+
+
 /*
 planning to make this a struct StandardLibrary with fields:
-- func_library: HashMap<String, Box<dyn FnMut<'a>(Env, Node) -> Result<Node, Err<'a>>>>
+- func_library: HashMap<String, Box<dyn FnMut<'a>(&Env, Node) -> Result<Node, Err<'a>>>>
 and methods:
 - fn create () -> StandardLibrary  (new stdlib with default functions)
-- fn search (env: Env, query: Node) -> Result<Node, Err>>>
-- fn add_func (func: Box<dyn FnMut(Env, Node) -> Result<Node, Err>>) -> Result<(), Err>
+- fn search (env: &Env, query: Node) -> Result<Node, Err>>>
+- fn add_func (func: Box<dyn FnMut(&Env, Node) -> Result<Node, Err>>) -> Result<(), Err>
 - fn rem_func (func_name: String) -> Result<(), Err>
 */
-pub fn search_library<'a> (env: Env, search: Node) -> Result<Node, Err<'a>> {
-	let name: &str = if let (n, "Symbol") = search.branches[0].id() { n }else { return Ok(Node::void()); };
-	
-	let mut evaluated = Vec::new();
-	for branch in search.branches[1].branches.iter() { evaluated.push(evaluate(env.clone(), *branch.clone())?); }
-	let arguments = Node::new(NodeClass::Field, [0, 0], evaluated);
-
+pub const LIBRARY: [&str; 39] = [
+	"raise", "exit", "write", "prompt", "out", "type", "stop", "reset", "span", "toNumber", "intersect", "len", "in",
+	"getenv", "setenv", "cwd", "now", "clock", "platform",
+	"read_text", "write_text", "append_text", "read_lines", "exists", "list_dir", "mkdir", "remove", "read_csv", "write_csv",
+	"from_json", "to_json", "load_npy", "save_npy", "load_npz", "save_npz",
+	"read_fits", "write_fits",
+	"save_workspace", "load_workspace"
+];
+
+
+// arguments are evaluated by the vm before the search
+pub fn search_library<'a> (env: &Env, name: &str, arguments: Node) -> Result<Node, Err<'a>> {
 	match name {
+		"raise" => raise(env, arguments),
+		"exit" => exit(env, arguments),
 		"write" => write(env, arguments),
 		"prompt" => prompt(env, arguments.validate_args_len(1)?),
 		"out" => out(env, arguments.validate_args_len(1)?),
@@ -183,7 +491,35 @@ pub fn search_library<'a> (env: Env, search: Node) -> Result<Node, Err<'a>> {
 		"intersect" => intersect(env, arguments.validate_args_len(2)?),
 		"len" => length(env, arguments.validate_args_len(1)?),
 		"in" => contains(env, arguments.validate_args_len(2)?),
+		
+		"getenv" => getenv(env, arguments),
+		"setenv" => setenv(env, arguments.validate_args_len(2)?),
+		"cwd" => cwd(env, arguments.validate_args_len(0)?),
+		"now" => now(env, arguments.validate_args_len(0)?),
+		"clock" => clock(env, arguments.validate_args_len(0)?),
+		"platform" => platform(env, arguments.validate_args_len(0)?),
+		
+		"read_text" => read_text(env, arguments.validate_args_len(1)?),
+		"write_text" => write_text(env, arguments.validate_args_len(2)?),
+		"append_text" => append_text(env, arguments.validate_args_len(2)?),
+		"read_lines" => read_lines(env, arguments.validate_args_len(1)?),
+		"exists" => exists(env, arguments.validate_args_len(1)?),
+		"list_dir" => list_dir(env, arguments.validate_args_len(1)?),
+		"mkdir" => mkdir(env, arguments.validate_args_len(1)?),
+		"remove" => remove(env, arguments.validate_args_len(1)?),
+		"read_csv" => read_csv(env, arguments),
+		"write_csv" => write_csv(env, arguments),
+		"from_json" => from_json(env, arguments.validate_args_len(1)?),
+		"to_json" => to_json(env, arguments),
+		"load_npy" => npy::load_npy(&arguments.validate_args_len(1)?.branches[0]),
+		"save_npy" => save_npy(env, arguments),
+		"load_npz" => npy::load_npz(&arguments.validate_args_len(1)?.branches[0]),
+		"save_npz" => save_npz(env, arguments),
+		"read_fits" => fits::read_fits(&arguments.validate_args_len(1)?.branches[0]),
+		"write_fits" => write_fits(env, arguments),
+		
+		"save_workspace" => save(env, arguments.validate_args_len(1)?),
+		"load_workspace" => load(env, arguments.validate_args_len(1)?),
 		_ => Ok(Node::void())
 	}
 }
-
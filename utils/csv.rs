@@ -0,0 +1,273 @@
+use std::fs;
+use crate::evaluator::NameSpace;
+use crate::utils::node::{ NodeClass, Node };
+use crate::utils::error::{ ErrorClass, Err };
+use crate::utils::builtin_funcs::parse_number;
+
+
+/*
+csv files are read as RFC 4180 records, fields can be quoted to hold delimiters, quotes ("") and line breaks.
+a file with a header becomes a Table instance with the fields header and rows, without one it's a list of rows.
+columns where every present value is a number are converted with the same rules as toNumber,
+empty unquoted fields and the markers listed in the missing option are Void.
+
+options are given as an object instance, every field is optional
+	delimiter	single character separating fields, ","
+	header		True or False, detected when Void
+	convert		False keeps every field as a String
+	missing		list of strings that mark a missing value
+write_csv takes the delimiter option, and a header option with a list of column names when the rows are a plain list
+*/
+
+
+struct Cell {
+	text: String,
+	quoted: bool
+}
+
+
+// the options instance as a namespace, missing fields fall back to their defaults
+fn options_of<'a> (options: Option<&Node>) -> Result<Option<NameSpace>, Err<'a>> {
+	match options {
+		None => Ok(None),
+		Some(node) if node.get_type() == "Void" => Ok(None),
+		Some(node) if node.get_type() == "ObjectInstance" && node.branches.len() == 2 => Ok(Some(NameSpace::from(node.branches[0].clone(), node.branches[1].clone())?)),
+		Some(node) => Err(Err::new(ErrorClass::TypeMismatch("ObjectInstance", node.get_type()), node.clone()))
+	}
+}
+
+
+fn option (options: &Option<NameSpace>, name: &str) -> Option<Node> {
+	let value = options.as_ref()?.get(Node::symbol(name.to_string())).ok()?;
+	if value.get_type() == "Void" { None }else { Some(value) }
+}
+
+
+fn delimiter_of<'a> (options: &Option<NameSpace>) -> Result<char, Err<'a>> {
+	let node = match option(options, "delimiter") {
+		Some(node) => node,
+		None => { return Ok(','); }
+	};
+	let text = node.into_string()?;
+	let mut chars = text.chars();
+	match (chars.next(), chars.next()) {
+		(Some(delim), None) if delim != '"' && delim != '\n' && delim != '\r' => Ok(delim),
+		_ => Err(Err::new(ErrorClass::CustomError(format!("'{}' is not a valid csv delimiter, expected one character other than a quote or line break", text)), node))
+	}
+}
+
+
+// splits the text into records, blank lines are skipped
+fn parse_records<'a> (text: &str, delim: char, path: &Node) -> Result<Vec<Vec<Cell>>, Err<'a>> {
+	let mut records = Vec::new();
+	let mut record: Vec<Cell> = Vec::new();
+	let mut cell = Cell { text: String::new(), quoted: false };
+	let mut chars = text.chars().peekable();
+	let mut line = 1;
+	let mut in_quotes = false;
+	let mut quote_line = 0;
+
+	while let Some(c) = chars.next() {
+		if in_quotes {
+			match c {
+				'"' if chars.peek() == Some(&'"') => { chars.next(); cell.text.push('"'); },
+				'"' => { in_quotes = false; },
+				_ => {
+					if c == '\n' { line += 1; }
+					cell.text.push(c);
+			}}
+			continue;
+		}
+		match c {
+			'"' if cell.text.is_empty() && !cell.quoted => {
+				in_quotes = true;
+				cell.quoted = true;
+				quote_line = line;
+			},
+			'\r' if chars.peek() == Some(&'\n') => (), // the \n ends the record
+			'\n' | '\r' => {
+				line += 1;
+				if !record.is_empty() || !cell.text.is_empty() || cell.quoted {
+					record.push(std::mem::replace(&mut cell, Cell { text: String::new(), quoted: false }));
+					records.push(std::mem::take(&mut record));
+			}},
+			_ if c == delim => { record.push(std::mem::replace(&mut cell, Cell { text: String::new(), quoted: false })); },
+			_ => { cell.text.push(c); } // quotes inside unquoted fields are kept as written
+	}}
+	if in_quotes { return Err(Err::file(format!("has a quote opened on line {} that is never closed", quote_line), path)); }
+	if !record.is_empty() || !cell.text.is_empty() || cell.quoted {
+		record.push(cell);
+		records.push(record);
+	}
+	Ok(records)
+}
+
+
+// the first record is a header if none of its fields are numbers or empty and some column below it is numeric
+fn detect_header (records: &[Vec<Cell>], missing: &[String]) -> bool {
+	let first = match records.first() {
+		Some(first) if records.len() > 1 => first,
+		_ => { return false; }
+	};
+	if first.iter().any(|cell| cell.text.is_empty() || parse_number(&cell.text).is_some()) { return false; }
+	(0..first.len()).any(|col| {
+		let mut present = records[1..].iter().filter_map(|record| record.get(col)).filter(|cell| !is_missing(cell, missing)).peekable();
+		present.peek().is_some() && present.all(|cell| parse_number(&cell.text).is_some())
+	})
+}
+
+
+fn is_missing (cell: &Cell, missing: &[String]) -> bool { (cell.text.is_empty() && !cell.quoted) || missing.contains(&cell.text) }
+
+
+pub fn read_csv<'a> (path: &Node, options: Option<&Node>) -> Result<Node, Err<'a>> {
+	let options = options_of(options)?;
+	let delim = delimiter_of(&options)?;
+	let convert = match option(&options, "convert") {
+		Some(node) => node.into_boolean()?,
+		None => true
+	};
+	let missing = match option(&options, "missing") {
+		Some(node) => node.validate_type("List")?.branches[0].branches.iter().map(|marker| marker.into_string()).collect::<Result<Vec<String>, Err>>()?,
+		None => Vec::new()
+	};
+	let text = match fs::read_to_string(path.into_string()?) {
+		Ok(text) => text,
+		Err(err) => { return Err(Err::file(format!("could not be read: {}", err), path)); }
+	};
+
+	let mut records = parse_records(text.strip_prefix('\u{feff}').unwrap_or(&text), delim, path)?;
+	let header = match option(&options, "header") {
+		Some(node) => node.into_boolean()?,
+		None => detect_header(&records, &missing)
+	};
+	let names = if header && !records.is_empty() { Some(records.remove(0)) }else { None };
+
+	// ragged rows are padded with Void to the widest row (or the header)
+	let width = records.iter().map(|record| record.len()).chain(names.iter().map(|names| names.len())).max().unwrap_or(0);
+	let numeric: Vec<bool> = (0..width).map(|col| {
+		convert && records.iter().filter_map(|record| record.get(col)).all(|cell| is_missing(cell, &missing) || parse_number(&cell.text).is_some())
+	}).collect();
+
+	let rows: Vec<Node> = records.iter().map(|record| {
+		Node::list((0..width).map(|col| match record.get(col) {
+			None => Node::void(),
+			Some(cell) if is_missing(cell, &missing) => Node::void(),
+			Some(cell) if numeric[col] => Node::number(parse_number(&cell.text).unwrap()),
+			Some(cell) => Node::string(cell.text.clone())
+		}).collect())
+	}).collect();
+
+	match names {
+		Some(names) => {
+			let header = Node::list(names.into_iter().map(|cell| Node::string(cell.text)).collect());
+			Ok(Node::instance("Table", vec![(String::from("header"), header), (String::from("rows"), Node::list(rows))]))
+		},
+		None => Ok(Node::list(rows))
+	}
+}
+
+
+// fields are quoted when they hold the delimiter, a quote or a line break, empty strings are quoted so they stay apart from Void
+fn encode_field<'a> (value: &Node, delim: char) -> Result<String, Err<'a>> {
+	let text = match *value.signature {
+		NodeClass::Void => { return Ok(String::new()); },
+		NodeClass::String(ref text) => text.clone(),
+		NodeClass::Number(_) | NodeClass::Boolean(_) => value.decode(),
+		_ => { return Err(Err::new(ErrorClass::Conversion(value.decode(), value.get_type(), "csv field"), value.clone())); }
+	};
+	if text.is_empty() || text.contains([delim, '"', '\n', '\r']) { Ok(format!("\"{}\"", text.replace('"', "\"\""))) }
+	else { Ok(text) }
+}
+
+
+fn encode_row<'a> (row: &Node, delim: char) -> Result<String, Err<'a>> {
+	let fields = row.validate_type("List")?.branches[0].branches.iter().map(|value| encode_field(value, delim)).collect::<Result<Vec<String>, Err>>()?;
+	Ok(fields.join(&delim.to_string()))
+}
+
+
+// rows is a list of lists or a Table instance, a header option is written before a plain list of rows
+pub fn write_csv<'a> (path: &Node, rows: &Node, options: Option<&Node>) -> Result<Node, Err<'a>> {
+	let options = options_of(options)?;
+	let delim = delimiter_of(&options)?;
+	let (header, rows) = match rows.get_type() {
+		"ObjectInstance" if rows.branches.len() == 2 => {
+			let table = NameSpace::from(rows.branches[0].clone(), rows.branches[1].clone())?;
+			(Some(table.get(Node::symbol("header".to_string()))?), table.get(Node::symbol("rows".to_string()))?)
+		},
+		_ => (option(&options, "header"), rows.clone())
+	};
+
+	let mut lines = Vec::new();
+	if let Some(header) = header { lines.push(encode_row(&header, delim)?); }
+	for row in rows.validate_type("List")?.branches[0].branches.iter() { lines.push(encode_row(row, delim)?); }
+	let text = lines.into_iter().map(|line| line + "\r\n").collect::<String>(); // records end with CRLF as RFC 4180 specifies
+	match fs::write(path.into_string()?, text) {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be written: {}", err), path))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::{ env, process };
+	use super::*;
+	
+	fn texts (text: &str) -> Vec<Vec<(String, bool)>> {
+		let records = parse_records(text, ',', &Node::string(String::from("<csv>"))).unwrap();
+		records.into_iter().map(|record| record.into_iter().map(|cell| (cell.text, cell.quoted)).collect()).collect()
+	}
+	
+	fn header (text: &str, missing: &[&str]) -> bool {
+		let records = parse_records(text, ',', &Node::string(String::from("<csv>"))).unwrap();
+		detect_header(&records, &missing.iter().map(|marker| marker.to_string()).collect::<Vec<String>>())
+	}
+	
+	#[test]
+	fn quoting () {
+		let cell = |text: &str, quoted: bool| (text.to_string(), quoted);
+		assert_eq!(texts("a,\"b,c\",\"say \"\"hi\"\"\",\"two\nlines\"\r\n1,,\"\",x\"y\n\n"), vec![
+			vec![cell("a", false), cell("b,c", true), cell("say \"hi\"", true), cell("two\nlines", true)],
+			vec![cell("1", false), cell("", false), cell("", true), cell("x\"y", false)]
+		]);
+		assert!(parse_records("a,\"b\n", ',', &Node::void()).is_err());
+		
+		let field = |value: Node| encode_field(&value, ',').unwrap();
+		assert_eq!(field(Node::string(String::from("plain"))), "plain");
+		assert_eq!(field(Node::string(String::from("a,b"))), "\"a,b\"");
+		assert_eq!(field(Node::string(String::from("say \"hi\""))), "\"say \"\"hi\"\"\"");
+		assert_eq!(field(Node::string(String::new())), "\"\"");
+		assert_eq!(field(Node::void()), "");
+	}
+	
+	#[test]
+	fn header_detection () {
+		assert!(header("name,age\nann,3\nbob,4\n", &[]));
+		assert!(header("x,y\n,1\n", &[]));
+		assert!(!header("1,2\n3,4\n", &[]));
+		assert!(!header("a,b\nc,d\n", &[])); // no numeric column to tell the header apart
+		assert!(!header("name,age\n", &[]));
+		assert!(!header("name,age\nann,NA\n", &["NA"]));
+		assert!(header("name,age\nann,NA\nbob,4\n", &["NA"]));
+	}
+	
+	#[test]
+	fn round_trip () {
+		let path = Node::string(env::temp_dir().join(format!("kylu-csv-{}.csv", process::id())).to_string_lossy().to_string());
+		let rows = Node::list(vec![
+			Node::list(vec![Node::string(String::from("a,b")), Node::number(1.5), Node::void()]),
+			Node::list(vec![Node::string(String::from("two\nlines")), Node::number(-2.0), Node::string(String::new())])
+		]);
+		let header = Node::list(vec![Node::string(String::from("text")), Node::string(String::from("num")), Node::string(String::from("opt"))]);
+		let options = Node::instance("Options", vec![(String::from("header"), header.clone())]);
+		write_csv(&path, &rows, Some(&options)).unwrap();
+		
+		let table = read_csv(&path, None).unwrap();
+		let table = NameSpace::from(table.branches[0].clone(), table.branches[1].clone()).unwrap();
+		assert!(table.get(Node::symbol(String::from("header"))).unwrap() == header);
+		assert!(table.get(Node::symbol(String::from("rows"))).unwrap() == rows);
+		fs::remove_file(path.into_string().unwrap()).unwrap();
+	}
+}
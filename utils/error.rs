@@ -1,5 +1,7 @@
 use std::error::Error;
-use std::{ fmt, process };
+use std::io::{ self, IsTerminal };
+use std::{ env, fmt, process };
+use std::sync::{ Arc, RwLock };
 use crate::utils::node::{ NodeClass, Node };
 
 
@@ -21,9 +23,11 @@ pub enum ErrorClass<'a> {
 	IndexError(f32, String), // index, list
 	ArgMismatch(usize, usize),
 	Signal(&'a str), // signal identifier
-	File(&'a str, String),
+	File(String, String), // reason, file
 	Conversion(String, &'a str, &'a str), // converted node, start type, end type
 	FatalError(String),
+	LimitExceeded(&'a str), // name of the exhausted execution limit
+	Raised(String, String), // kind, message, raised by the user with raise()
 	CustomError(String) // used for errors that only apply in one particular context
 }
 
@@ -36,65 +40,293 @@ pub struct Err<'a> {
 impl <'a> Err<'a> {
 	pub fn new (class: ErrorClass<'a>, cause: Node) -> Err<'a> { Err { class: Box::new(class), cause } }
 	
-	// enable user interaction with error values
-	pub fn to_node(&self) -> Result<Node, Err<'a>> {
+	// a File error about the file named by the path node, which becomes the cause
+	pub fn file (reason: String, path: &Node) -> Err<'a> { Err::new(ErrorClass::File(reason, path.decode()), path.clone()) }
+	
+	// enable user interaction with error values, an Error instance with the fields
+	// kind, message, cause, file, line and col (line and col are void if the span isn't in the source)
+	pub fn to_node(&self, file: &str, source: &str, span: [usize; 2]) -> Result<Node, Err<'a>> {
+		match *self.class {
+			ErrorClass::MissingSeperator(_, _) | ErrorClass::UnmatchedBracket(_) | ErrorClass::UnknownToken |
+			ErrorClass::ResolutionFailure(_, _) | ErrorClass::EndOfFile | ErrorClass::UnknownSyntax(_) => {
+				return Err(Err::new(ErrorClass::CustomError("Parsing errors cannot be converted to nodes".to_string()), Node::void()));
+			},
+			_ => ()
+		}
+		let (file, line, col) = Position::locate(source, file, span, |pos, file| match pos {
+			Some(pos) => (file.to_string(), Node::number(pos.line as f32), Node::number(pos.col as f32)),
+			None => (file.to_string(), Node::void(), Node::void())
+		});
+		let message = match *self.class {
+			ErrorClass::Raised(_, ref msg) => msg.clone(), // the kind is already a field
+			_ => self.message()
+		};
+		let values = vec![Node::string(self.name().to_string()), Node::string(message), self.cause.clone(), Node::string(file.to_string()), line, col];
+		Ok(Node::instance("Error", ["kind", "message", "cause", "file", "line", "col"].iter().map(|name| name.to_string()).zip(values).collect()))
+	}
+	
+	// class name used by to_node and in diagnostics
+	pub fn name (&self) -> &str {
 		match *self.class {
-			ErrorClass::VoidReference(_) => { return Ok(Node::string("VoidReference".to_string())); },
-			ErrorClass::UndefinedOperation(_, _, _) => { return Ok(Node::string("UndefinedOperation".to_string())); },
-			ErrorClass::TypeMismatch(_, _) => { return Ok(Node::string("TypeMismatch".to_string())); },
-			ErrorClass::IndexError(_, _) => { return Ok(Node::string("IndexError".to_string())); },
-			ErrorClass::ArgMismatch(_, _) => { return Ok(Node::string("ArgMismatch".to_string())); },
-			ErrorClass::Signal(_) => { return Ok(Node::string("Signal".to_string())); },
-			ErrorClass::File(_, _) => { return Ok(Node::string("File".to_string())); },
-			ErrorClass::Conversion(_, _, _) => { return Ok(Node::string("Conversion".to_string())); },
-			ErrorClass::FatalError(_) => { return Ok(Node::string("FatalError".to_string())); },
-			ErrorClass::CustomError(_) => { return Ok(Node::string("CustomError".to_string())); },
-			_ => { return Err(Err::new(ErrorClass::CustomError("Parsing errors cannot be converted to nodes".to_string()), Node::void())); }
+			ErrorClass::MissingSeperator(_, _) => "MissingSeperator",
+			ErrorClass::UnmatchedBracket(_) => "UnmatchedBracket",
+			ErrorClass::UnknownToken => "UnknownToken",
+			ErrorClass::ResolutionFailure(_, _) => "ResolutionFailure",
+			ErrorClass::EndOfFile => "EndOfFile",
+			ErrorClass::UnknownSyntax(_) => "UnknownSyntax",
+			
+			ErrorClass::VoidReference(_) => "VoidReference",
+			ErrorClass::UndefinedOperation(_, _, _) => "UndefinedOperation",
+			ErrorClass::TypeMismatch(_, _) => "TypeMismatch",
+			ErrorClass::IndexError(_, _) => "IndexError",
+			ErrorClass::ArgMismatch(_, _) => "ArgMismatch",
+			ErrorClass::Signal(_) => "Signal",
+			ErrorClass::File(_, _) => "File",
+			ErrorClass::Conversion(_, _, _) => "Conversion",
+			ErrorClass::FatalError(_) => "FatalError",
+			ErrorClass::LimitExceeded(_) => "LimitExceeded",
+			ErrorClass::Raised(ref kind, _) => kind,
+			ErrorClass::CustomError(_) => "CustomError"
 		}
 	}
 
 	
-	// used for errors during parsing before context can be determined
-	pub fn parse_err (class: ErrorClass<'a>) -> Err<'a> { Err { class: Box::new(class), cause: Node::void() } }
+	// used for errors during parsing, the cause is the token the parser stopped at
+	pub fn parse_err (class: ErrorClass<'a>, token: Node) -> Err<'a> { Err { class: Box::new(class), cause: token } }
 	
-	pub fn throw (&self, source: String, trace: Vec<(NodeClass, [usize; 2])>, halt: bool) -> Node {
-		
-		eprintln!("----------------------------------- AN UNHANDLED EXCEPTION HAS OCCURRED! --------");
+	pub fn message (&self) -> String {
 		match *self.class {
 			// parsing errors
-			ErrorClass::MissingSeperator(ref sep, ref before) => eprintln!("Expected seperator '{}' after element {}", sep, before),
-			ErrorClass::UnmatchedBracket(ref bracket) => eprintln!("Bracket '{}' was never closed", bracket),
-			ErrorClass::UnknownToken => eprintln!("Unrecognized token#"),
-			ErrorClass::ResolutionFailure(elem, ref reason) => eprintln!("Failed to resolve element {}, {}", elem, reason),
-			ErrorClass::EndOfFile => eprintln!("Scanner reached the end of the source file"),
-			ErrorClass::UnknownSyntax(ref token) => println!("Unrecognized syntax identifier '{}'", token),
+			ErrorClass::MissingSeperator(ref sep, ref before) => format!("Expected seperator '{}' after element {}", sep, before),
+			ErrorClass::UnmatchedBracket(ref bracket) => format!("Bracket '{}' was never closed", bracket),
+			ErrorClass::UnknownToken => "Unrecognized token#".to_string(),
+			ErrorClass::ResolutionFailure(elem, ref reason) => format!("Failed to resolve element {}, {}", elem, reason),
+			ErrorClass::EndOfFile => "Scanner reached the end of the source file".to_string(),
+			ErrorClass::UnknownSyntax(ref token) => format!("Unrecognized syntax identifier '{}'", token),
 			
 			// runtime errors
-			ErrorClass::VoidReference(ref reference) => eprintln!("Reference '{}' has no associated value", reference),
-			ErrorClass::UndefinedOperation(ref operation, l_op, r_op) => eprintln!("Operation '{}' is not defined for types {}, {}", operation, l_op, r_op), 
-			ErrorClass::TypeMismatch(expected, found) => eprintln!("Expected type '{}', found type '{}'", expected, found),
-			ErrorClass::IndexError(index, ref list) => eprintln!("Index {} is out of range for list {}",index, list),
-			ErrorClass::ArgMismatch(expected, found) => eprintln!("Expected {} arguments, found {}", expected, found),
-			ErrorClass::Signal(name) => eprintln!("Signal '{}' cannot be invoked outside it's associated block", name),
-			ErrorClass::File(err, ref file) => eprintln!("An error occurred while processing the file '{}' {}", file, err),
-			ErrorClass::Conversion(ref target, init, end) => eprintln!("{} '{}' cannot be converted to type {}", init, target, end),
-			ErrorClass::FatalError(ref msg) => eprintln!("An unrecoverable error has occurred! {}", msg),
-			ErrorClass::CustomError(ref msg) => eprintln!("{}", msg)
+			ErrorClass::VoidReference(ref reference) => format!("Reference '{}' has no associated value", reference),
+			ErrorClass::UndefinedOperation(ref operation, l_op, r_op) => format!("Operation '{}' is not defined for types {}, {}", operation, l_op, r_op), 
+			ErrorClass::TypeMismatch(expected, found) => format!("Expected type '{}', found type '{}'", expected, found),
+			ErrorClass::IndexError(index, ref list) => format!("Index {} is out of range for list {}",index, list),
+			ErrorClass::ArgMismatch(expected, found) => format!("Expected {} arguments, found {}", expected, found),
+			ErrorClass::Signal("KeyboardInterrupt") => "Execution was interrupted".to_string(),
+			ErrorClass::Signal(name) => format!("Signal '{}' cannot be invoked outside it's associated block", name),
+			ErrorClass::File(ref err, ref file) => format!("An error occurred while processing the file '{}' {}", file, err),
+			ErrorClass::Conversion(ref target, init, end) => format!("{} '{}' cannot be converted to type {}", init, target, end),
+			ErrorClass::FatalError(ref msg) => format!("An unrecoverable error has occurred! {}", msg),
+			ErrorClass::LimitExceeded(limit) => format!("Execution was stopped after exceeding the {} limit", limit),
+			ErrorClass::Raised(ref kind, ref msg) => if msg.is_empty() { kind.clone() }else { format!("{}: {}", kind, msg) },
+			ErrorClass::CustomError(ref msg) => msg.clone()
 		}
-		println!("---------------------------------------------------------------------------------");
-		let mut source_slice = "";
-		for call in trace.iter() {
-			source_slice = &source[call.1[0]..call.1[1]];
-			eprintln!("[-] ({}, {})----{}-> {}\n", call.1[0], call.1[1], Node::new(call.0.clone(), [0, 0], Vec::new()).show(), source_slice);
+	}
+	
+	// the span an error is reported at, the innermost trace frame or the cause for errors without a trace (syntax errors)
+	pub fn location (&self, trace: &[(NodeClass, [usize; 2])]) -> [usize; 2] {
+		match trace.last() {
+			Some(frame) => frame.1,
+			None => self.cause.span
 		}
+	}
+	
+	// source is the code as parsed by eval_file (wrapped in {...}$), positions are reported for the unwrapped code
+	pub fn throw (&self, source: String, name: &str, trace: Vec<(NodeClass, [usize; 2])>, halt: bool) -> Node {
+		let paint = Painter::for_stderr();
+		eprintln!("{}: {}", paint.red("error"), paint.bold(&self.message()));
+		
+		let span = self.location(&trace);
+		Position::locate(&source, name, span, |pos, file| match pos {
+			Some(pos) => {
+				let number = pos.line.to_string();
+				let gutter = " ".repeat(number.len());
+				eprintln!("{}{} {}:{}:{}", gutter, paint.blue("-->"), file, pos.line, pos.col);
+				eprintln!("{} {}", gutter, paint.blue("|"));
+				eprintln!("{} {} {}", paint.blue(&number), paint.blue("|"), pos.text);
+				eprintln!("{} {} {}{}", gutter, paint.blue("|"), " ".repeat(pos.col - 1), paint.red(&"^".repeat(pos.width)));
+			},
+			None => { if span != [0, 0] { eprintln!(" {} {}", paint.blue("-->"), file); } } // span from another source
+		});
 		
+		// short trace, innermost call first, recursion shows up as one frame repeated
+		let mut frames: Vec<(String, usize)> = Vec::new();
+		for frame in trace.iter().rev().skip(1) {
+			let text = Position::locate(&source, name, frame.1, |pos, file| match pos {
+				Some(pos) => format!("{}:{}:{}  {}", file, pos.line, pos.col, pos.excerpt()),
+				None => String::from("<other source>")
+			});
+			match frames.last_mut() {
+				Some((last, count)) if *last == text => { *count += 1; },
+				_ => { frames.push((text, 1)); }
+		}}
+		for (frame, count) in frames.iter().take(TRACE_LENGTH) {
+			if *count > 1 { eprintln!("  {} {} ({} times)", paint.blue("in"), frame, count); }
+			else { eprintln!("  {} {}", paint.blue("in"), frame); }
+		}
+		if frames.len() > TRACE_LENGTH { eprintln!("  {} {} more", paint.blue("..."), frames.len() - TRACE_LENGTH); }
+		eprintln!();
+		
+		if halt { process::exit(1); }
+		Node::void()
+	}
+	
+	// one line json diagnostic, trace frames are listed innermost first like in throw()
+	pub fn to_json (&self, source: &str, name: &str, trace: &[(NodeClass, [usize; 2])]) -> String {
+		let frames: Vec<String> = trace.iter().rev().map(|frame| {
+			format!("{{\"node\":{},{}}}", json_string(&Node::new(frame.0.clone(), [0, 0], Vec::new()).show()), json_span(source, name, frame.1))
+		}).collect();
+		format!("{{\"class\":{},\"message\":{},{},\"trace\":[{}]}}",
+			json_string(self.name()),
+			json_string(&self.message()),
+			json_span(source, name, self.location(trace)),
+			frames.join(",")
+		)
+	}
+	
+	pub fn throw_json (&self, source: String, name: &str, trace: Vec<(NodeClass, [usize; 2])>, halt: bool) -> Node {
+		eprintln!("{}", self.to_json(&source, name, &trace));
 		if halt { process::exit(1); }
 		Node::void()
 	}
+	
+	// report the error in the given format
+	pub fn report (&self, format: ErrorFormat, source: String, name: &str, trace: Vec<(NodeClass, [usize; 2])>, halt: bool) -> Node {
+		match format {
+			ErrorFormat::Text => self.throw(source, name, trace, halt),
+			ErrorFormat::Json => self.throw_json(source, name, trace, halt)
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorFormat {
+	#[default]
+	Text,
+	Json
+}
+
+
+pub fn json_string (text: &str) -> String {
+	let mut out = String::from("\"");
+	for c in text.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c)
+		}
+	}
+	out.push('"');
+	out
+}
+
+
+// file and span fields, the span is null if it doesn't belong to a known source (parse errors without a cause)
+fn json_span (source: &str, name: &str, span: [usize; 2]) -> String {
+	Position::locate(source, name, span, |pos, file| match pos {
+		Some(pos) => format!("\"file\":{},\"span\":{{\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{}}}", json_string(file), pos.line, pos.col, pos.end_line, pos.end_col),
+		None => format!("\"file\":{},\"span\":null", json_string(file))
+	})
+}
+
+
+const TRACE_LENGTH: usize = 5; // frames shown below the error's own location
+const WRAPPER: usize = 1; // length of the { eval_file puts in front of the source
+
+// every file and terminal input is parsed on its own, their spans are moved past this point so that
+// frames from all of them can share one trace and still be traced back to their source
+pub const MODULE_BASE: usize = usize::MAX / 2 + 1;
+static SOURCES: RwLock<Vec<(usize, String, Arc<str>)>> = RwLock::new(Vec::new()); // base, file name, wrapped source
+
+
+// offset for the spans of a newly parsed source
+pub fn register_source (name: &str, source: &str) -> usize {
+	let mut sources = SOURCES.write().unwrap();
+	let base = sources.last().map(|(base, _, text)| base + text.len() + 1).unwrap_or(MODULE_BASE);
+	sources.push((base, name.to_string(), Arc::from(source)));
+	base
+}
+
+
+// 1-based line and column of a span's start, with the line it starts on
+pub struct Position<'s> {
+	pub line: usize,
+	pub col: usize,
+	pub end_line: usize, // end of the span without trailing whitespace, the column is exclusive
+	pub end_col: usize,
+	pub width: usize, // characters underlined, spans running past the end of the line stop there
+	pub text: &'s str
+}
+
+impl <'s> Position<'s> {
+	// position of a span in whichever file it came from, the given source unless the span belongs to a module
+	pub fn locate<R> (source: &str, name: &str, span: [usize; 2], found: impl FnOnce(Option<Position>, &str) -> R) -> R {
+		if span[0] < MODULE_BASE { return found(Position::find(source, span), name); }
+		let sources = SOURCES.read().unwrap();
+		match sources.iter().rev().find(|(base, _, _)| span[0] >= *base) {
+			Some((base, file, text)) => found(Position::find(text, [span[0] - base, span[1] - base]), file),
+			None => found(None, name) // saved in a workspace by another process
+		}
+	}
+	
+	pub fn find (source: &'s str, span: [usize; 2]) -> Option<Position<'s>> {
+		if span == [0, 0] || span[1] < span[0] || span[1] > source.len() || !source.is_char_boundary(span[0]) { return None; }
+		let code = source.get(WRAPPER..source.len().saturating_sub(2))?; // drop the {...}$ wrapper
+		let start = span[0].saturating_sub(WRAPPER).min(code.len());
+		let end = span[1].saturating_sub(WRAPPER).min(code.len());
+		if !code.is_char_boundary(start) || !code.is_char_boundary(end) { return None; }
+		
+		let line_start = code[..start].rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+		let line_end = code[start..].find('\n').map(|idx| start + idx).unwrap_or(code.len());
+		let text = &code[line_start..line_end];
+		let underlined = code[start..end.min(line_end).max(start)].trim_end();
+		let end = start + code[start..end].trim_end().len();
+		let end_line_start = code[..end].rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+		Some(Position {
+			line: code[..start].matches('\n').count() + 1,
+			col: code[line_start..start].chars().count() + 1,
+			end_line: code[..end].matches('\n').count() + 1,
+			end_col: code[end_line_start..end].chars().count() + 1,
+			width: underlined.chars().count().max(1),
+			text
+		})
+	}
+	
+	// the spanned code, cut at the end of its first line and short enough for a trace
+	pub fn excerpt (&self) -> String {
+		let index = |col: usize| self.text.char_indices().nth(col - 1).map(|(idx, _)| idx).unwrap_or(self.text.len());
+		let from = index(self.col);
+		let to = if self.end_line == self.line { index(self.end_col).max(from) }else { self.text.len() };
+		let code = self.text[from..to].trim();
+		match code.char_indices().nth(40) {
+			Some((idx, _)) => format!("{}...", &code[..idx]),
+			None if self.end_line > self.line => format!("{}...", code),
+			None => code.to_string()
+		}
+	}
+}
+
+
+// ansi colors, only used when stderr is a terminal and NO_COLOR isn't set
+struct Painter { color: bool }
+
+impl Painter {
+	fn for_stderr () -> Painter { Painter { color: io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none() } }
+	
+	fn paint (&self, code: &str, text: &str) -> String {
+		if self.color { format!("\x1b[{}m{}\x1b[0m", code, text) }else { text.to_string() }
+	}
+	
+	fn red (&self, text: &str) -> String { self.paint("1;31", text) }
+	fn blue (&self, text: &str) -> String { self.paint("1;34", text) }
+	fn bold (&self, text: &str) -> String { self.paint("1", text) }
 }
 
 impl fmt::Display for Err<'_> {
-	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "greetings mortals!!") }
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}: {}", self.name(), self.message()) }
 }
 
 impl Error for Err<'_> {}
@@ -0,0 +1,601 @@
+use std::fs;
+use crate::evaluator::NameSpace;
+use crate::utils::node::{ NodeClass, Node };
+use crate::utils::error::{ ErrorClass, Err };
+use crate::utils::npy::{ nest, elements, flatten_array };
+
+
+/*
+fits files are a series of HDUs, each an ascii header of 80 character cards in 2880 byte blocks followed by big endian data.
+read_fits gives a list of Hdu instances with the fields
+	type		PRIMARY, IMAGE, BINTABLE or the extension's XTENSION value
+	header		a Header instance of keyword values, COMMENT and HISTORY cards are collected into lists
+	data		images as nested lists (NAXIS1 is the innermost), binary tables as a list of columns, otherwise Void
+	names		binary tables only, the TTYPE of each column
+image pixels are scaled by BSCALE and BZERO and BLANK pixels are Void, table columns are scaled by TSCAL and TZERO.
+write_fits writes one image with an optional header instance, or a list of image Hdus such as read_fits returns
+*/
+const BLOCK: usize = 2880;
+const CARD: usize = 80;
+const MAX_NAXIS: usize = 999; // the standard's limit on axes
+
+// written from the data or not valid after rewriting it
+const STRUCTURAL: [&str; 9] = ["SIMPLE", "XTENSION", "BITPIX", "NAXIS", "EXTEND", "PCOUNT", "GCOUNT", "CHECKSUM", "DATASUM"];
+
+
+// exact values of a header's integer keywords, numbers in a Header only keep f32 precision
+type Integers = Vec<(String, usize)>;
+
+
+// keyword values in header order, a repeated keyword keeps its last value
+struct Header {
+	cards: Vec<(String, Node)>
+}
+
+impl Header {
+	fn get (&self, key: &str) -> Option<&Node> { self.cards.iter().find(|(name, _)| name == key).map(|(_, value)| value) }
+
+	fn number (&self, key: &str) -> Option<f64> {
+		match *self.get(key)?.signature {
+			NodeClass::Number(num) => Some(num as f64),
+			_ => None
+	}}
+
+	fn set (&mut self, key: String, value: Node) {
+		match self.cards.iter_mut().find(|(name, _)| *name == key) {
+			Some(card) => { card.1 = value; },
+			None => { self.cards.push((key, value)); }
+	}}
+}
+
+
+// the value field of a card (columns 11 to 80) without its comment
+fn parse_value (field: &str) -> (Node, Option<usize>) {
+	let field = field.trim_start();
+	if let Some(quoted) = field.strip_prefix('\'') {
+		let mut text = String::new();
+		let mut chars = quoted.chars().peekable();
+		while let Some(c) = chars.next() {
+			if c == '\'' {
+				if chars.peek() == Some(&'\'') { chars.next(); }else { break; }
+			}
+			text.push(c);
+		}
+		return (Node::string(text.trim_end().to_string()), None);
+	}
+	let value = field.split('/').next().unwrap_or("").trim();
+	let node = match value {
+		"" => Node::void(),
+		"T" => Node::boolean(true),
+		"F" => Node::boolean(false),
+		_ if value.starts_with('(') => { // complex values are a pair
+			let parts: Option<Vec<Node>> = value.trim_matches(|c| c == '(' || c == ')').split(',').map(|part| part.trim().replace(['D', 'd'], "E").parse::<f32>().ok().map(Node::number)).collect();
+			parts.map(Node::list).unwrap_or_else(|| Node::string(value.to_string()))
+		},
+		_ => match value.parse::<i64>() {
+			Ok(int) => { return (Node::number(int as f32), usize::try_from(int).ok()); },
+			Err(_) => match value.replace(['D', 'd'], "E").parse::<f32>() {
+				Ok(num) => Node::number(num),
+				Err(_) => Node::string(value.to_string())
+		}}
+	};
+	(node, None)
+}
+
+
+// reads the cards up to END, returns the header, the exact values of its integer keywords (NAXIS, THEAP... can be
+// past f32 precision) and the offset after its last block
+fn read_header<'a> (bytes: &[u8], start: usize, path: &Node) -> Result<(Header, Integers, usize), Err<'a>> {
+	let mut header = Header { cards: Vec::new() };
+	let mut integers = Vec::new();
+	let mut comments: Vec<(String, Vec<Node>)> = Vec::new();
+	let mut continued: Option<String> = None; // keyword of a long string that may go on in CONTINUE cards
+	let mut at = start;
+	loop {
+		let card = match bytes.get(at..at + CARD) {
+			Some(card) => card.iter().map(|byte| if byte.is_ascii() { *byte as char }else { '?' }).collect::<String>(), // cards are ascii, this keeps slicing safe
+			None => { return Err(Err::file(String::from("is not a valid fits file: a header has no END card"), path)); }
+		};
+		at += CARD;
+		let key = card[..8].trim_end().to_string();
+		if key == "END" { break; }
+
+		if key == "CONTINUE" {
+			if let Some(name) = continued.take() {
+				let (part, _) = parse_value(&card[8..]);
+				if let (Some(NodeClass::String(text)), Ok(part)) = (header.get(&name).map(|value| (*value.signature).clone()), part.into_string()) {
+					let joined = text.strip_suffix('&').unwrap_or(&text).to_string() + &part;
+					if joined.ends_with('&') { continued = Some(name.clone()); }
+					header.set(name, Node::string(joined));
+			}}
+			continue;
+		}
+		continued = None;
+		if &card[8..10] != "= " {
+			if key == "COMMENT" || key == "HISTORY" {
+				let text = Node::string(card[8..].trim_end().to_string());
+				match comments.iter_mut().find(|(name, _)| *name == key) {
+					Some((_, lines)) => { lines.push(text); },
+					None => { comments.push((key, vec![text])); }
+			}}
+			continue;
+		}
+		let (value, integer) = parse_value(&card[10..]);
+		if let Some(integer) = integer { integers.push((key.clone(), integer)); }
+		if let NodeClass::String(ref text) = *value.signature { if text.ends_with('&') { continued = Some(key.clone()); } }
+		header.set(key, value);
+	}
+	for (key, lines) in comments.into_iter() { header.set(key, Node::list(lines)); }
+	let end = start + (at - start).div_ceil(BLOCK) * BLOCK;
+	Ok((header, integers, end))
+}
+
+
+// a big endian number of the given fits type, B I J K E D (BITPIX 8 16 32 64 -32 -64)
+fn read_raw (bytes: &[u8], kind: u8) -> f64 {
+	match kind {
+		b'B' => bytes[0] as f64,
+		b'I' => i16::from_be_bytes([bytes[0], bytes[1]]) as f64,
+		b'J' => i32::from_be_bytes(bytes[..4].try_into().unwrap()) as f64,
+		b'K' => i64::from_be_bytes(bytes[..8].try_into().unwrap()) as f64,
+		b'E' => f32::from_be_bytes(bytes[..4].try_into().unwrap()) as f64,
+		_ => f64::from_be_bytes(bytes[..8].try_into().unwrap())
+	}
+}
+
+
+fn kind_size (kind: u8) -> Option<usize> {
+	match kind {
+		b'L' | b'B' | b'A' | b'X' => Some(1),
+		b'I' => Some(2),
+		b'J' | b'E' => Some(4),
+		b'K' | b'D' | b'C' | b'P' => Some(8),
+		b'M' | b'Q' => Some(16),
+		_ => None
+	}
+}
+
+
+struct Scaling {
+	scale: f64,
+	zero: f64,
+	null: Option<f64> // raw value marking a missing integer
+}
+
+impl Scaling {
+	fn apply (&self, raw: f64) -> Node {
+		if self.null == Some(raw) { return Node::void(); }
+		Node::number((raw * self.scale + self.zero) as f32)
+	}
+}
+
+
+fn read_image<'a> (header: &Header, integers: &[(String, usize)], data: &[u8], path: &Node) -> Result<Node, Err<'a>> {
+	let exact = |key: &str| integers.iter().rev().find(|(name, _)| name == key).map(|(_, value)| *value);
+	let naxis = exact("NAXIS").unwrap_or(0);
+	if naxis == 0 { return Ok(Node::void()); }
+	let kind = match header.number("BITPIX") {
+		Some(8.0) => b'B',
+		Some(16.0) => b'I',
+		Some(32.0) => b'J',
+		Some(64.0) => b'K',
+		Some(-32.0) => b'E',
+		Some(-64.0) => b'D',
+		_ => { return Err(Err::file(String::from("has an image with an unsupported BITPIX"), path)); }
+	};
+	let mut shape = (1..=naxis).map(|axis| exact(&format!("NAXIS{}", axis)).unwrap_or(0)).collect::<Vec<usize>>();
+	shape.reverse(); // NAXIS1 varies fastest
+	let size = kind_size(kind).unwrap();
+	let count = elements(&shape).ok_or_else(|| Err::file(String::from("has an image with too many or too large NAXIS values"), path))?;
+	if count.checked_mul(size).is_none_or(|len| data.len() < len) { return Err(Err::file(String::from("is truncated, an image is shorter than its NAXIS values"), path)); }
+
+	let scaling = Scaling {
+		scale: header.number("BSCALE").unwrap_or(1.0),
+		zero: header.number("BZERO").unwrap_or(0.0),
+		null: if kind == b'E' || kind == b'D' { None }else { header.number("BLANK") }
+	};
+	let mut pixels = data.chunks_exact(size).take(count).map(|raw| scaling.apply(read_raw(raw, kind)));
+	Ok(nest(&shape, &mut pixels))
+}
+
+
+// TFORMn is rTa, a repeat count, a type code and for variable length arrays the element type and a maximum
+struct Column {
+	repeat: usize,
+	kind: u8,
+	element: u8, // the heap element type of P and Q columns
+	offset: usize, // within the row
+	scaling: Scaling
+}
+
+
+// the values of one cell, a single value unless the repeat count (or heap count) isn't 1
+fn read_cell (bytes: &[u8], kind: u8, count: usize, scaling: &Scaling) -> Option<Node> {
+	let size = kind_size(kind)?;
+	let bytes = bytes.get(..if kind == b'X' { count.div_ceil(8) }else { count.checked_mul(size)? })?;
+	let values: Vec<Node> = match kind {
+		b'A' => { // strings end at the first null
+			let text = String::from_utf8_lossy(bytes);
+			return Some(Node::string(text.split('\0').next().unwrap_or("").trim_end().to_string()));
+		},
+		b'L' => bytes.iter().map(|byte| match byte {
+			b'T' => Node::boolean(true),
+			b'F' => Node::boolean(false),
+			_ => Node::void()
+		}).collect(),
+		b'X' => (0..count).map(|bit| Node::boolean(bytes[bit / 8] & (0x80 >> (bit % 8)) != 0)).collect(),
+		b'C' | b'M' => { // complex values are [real, imaginary]
+			let part = if kind == b'C' { b'E' }else { b'D' };
+			bytes.chunks_exact(size).map(|pair| Node::list(pair.chunks_exact(size / 2).map(|half| Node::number(read_raw(half, part) as f32)).collect())).collect()
+		},
+		_ => bytes.chunks_exact(size).map(|raw| scaling.apply(read_raw(raw, kind))).collect()
+	};
+	Some(match values.len() {
+		0 => Node::void(),
+		1 => values.into_iter().next().unwrap(),
+		_ => Node::list(values)
+	})
+}
+
+
+fn read_table<'a> (header: &Header, integers: &[(String, usize)], data: &[u8], path: &Node) -> Result<(Node, Node), Err<'a>> {
+	let exact = |key: &str| integers.iter().rev().find(|(name, _)| name == key).map(|(_, value)| *value);
+	let malformed = |reason: &str| Err::file(format!("has an invalid binary table: {}", reason), path);
+	let row_size = exact("NAXIS1").unwrap_or(0);
+	let rows = exact("NAXIS2").unwrap_or(0);
+	let fields = exact("TFIELDS").unwrap_or(0);
+	let table = match row_size.checked_mul(rows) {
+		Some(len) if len <= data.len() => len,
+		_ => { return Err(Err::file(String::from("is truncated, a table is shorter than its NAXIS values"), path)); }
+	};
+	// the data only bounds the row count when rows take up space
+	if row_size == 0 && rows > 0 && fields > 0 { return Err(malformed("NAXIS1 is 0 but it has rows and columns")); }
+	let heap = exact("THEAP").unwrap_or(table);
+
+	let mut columns = Vec::new();
+	let mut names = Vec::new();
+	let mut offset = 0;
+	for field in 1..=fields {
+		let form = match header.get(&format!("TFORM{}", field)).map(|form| form.into_string()) {
+			Some(Ok(form)) => form.trim().to_string(),
+			_ => { return Err(malformed(&format!("column {} has no TFORM", field))); }
+		};
+		let digits = form.chars().take_while(|c| c.is_ascii_digit()).count();
+		let repeat = if digits == 0 { 1 }else { form[..digits].parse::<usize>().map_err(|_| malformed(&format!("TFORM{} '{}'", field, form)))? };
+		let kind = form.as_bytes().get(digits).copied().ok_or_else(|| malformed(&format!("TFORM{} '{}'", field, form)))?;
+		let element = form.as_bytes().get(digits + 1).copied().unwrap_or(b' ');
+		let size = kind_size(kind).ok_or_else(|| malformed(&format!("TFORM{} '{}' has an unknown type", field, form)))?;
+		if (kind == b'P' || kind == b'Q') && kind_size(element).is_none() { return Err(malformed(&format!("TFORM{} '{}' has an unknown element type", field, form))); }
+
+		let number = |key: &str| header.number(&format!("{}{}", key, field));
+		columns.push(Column {
+			repeat,
+			kind,
+			element,
+			offset,
+			scaling: Scaling { scale: number("TSCAL").unwrap_or(1.0), zero: number("TZERO").unwrap_or(0.0), null: number("TNULL") }
+		});
+		let width = if kind == b'X' { Some(repeat.div_ceil(8)) }else { repeat.checked_mul(size) };
+		offset = width.and_then(|width| offset.checked_add(width)).ok_or_else(|| malformed(&format!("TFORM{} '{}' is too wide", field, form)))?;
+		names.push(match header.get(&format!("TTYPE{}", field)) {
+			Some(name) if name.get_type() == "String" => name.clone(),
+			_ => Node::string(format!("col{}", field))
+		});
+	}
+	if offset > row_size { return Err(malformed("its columns are wider than NAXIS1")); }
+
+	let mut values = Vec::new();
+	for column in columns.iter() {
+		let mut cells = Vec::with_capacity(rows);
+		for row in 0..rows {
+			let at = row * row_size + column.offset;
+			let cell = match column.kind {
+				b'P' | b'Q' if column.repeat > 0 => { // count and offset of the array in the heap
+					let wide = column.kind == b'Q';
+					let count = read_raw(&data[at..], if wide { b'K' }else { b'J' }) as usize;
+					let start = read_raw(&data[at + if wide { 8 }else { 4 }..], if wide { b'K' }else { b'J' }) as usize;
+					heap.checked_add(start).and_then(|start| data.get(start..)).and_then(|bytes| read_cell(bytes, column.element, count, &column.scaling)).map(|cell| match cell.get_type() { // always a list since the length varies
+						"Void" if count == 0 => Node::list(Vec::new()),
+						"List" | "String" if count != 1 => cell,
+						_ => Node::list(vec![cell])
+					})
+				},
+				b'P' | b'Q' => Some(Node::void()),
+				kind => read_cell(&data[at..], kind, column.repeat, &column.scaling)
+			};
+			cells.push(cell.ok_or_else(|| malformed("a variable length array is outside the heap"))?);
+		}
+		values.push(Node::list(cells));
+	}
+	Ok((Node::list(names), Node::list(values)))
+}
+
+
+pub fn read_fits<'a> (path: &Node) -> Result<Node, Err<'a>> {
+	let bytes = match fs::read(path.into_string()?) {
+		Ok(bytes) => bytes,
+		Err(err) => { return Err(Err::file(format!("could not be read: {}", err), path)); }
+	};
+	if !bytes.starts_with(b"SIMPLE  =") { return Err(Err::file(String::from("is not a valid fits file: it doesn't start with SIMPLE"), path)); }
+
+	let mut hdus = Vec::new();
+	let mut at = 0;
+	// the primary header is always read so a short file is reported, a partial block after the last hdu is ignored
+	while at == 0 || at + BLOCK <= bytes.len() {
+		let (header, integers, start) = read_header(&bytes, at, path)?;
+		let exact = |key: &str| integers.iter().rev().find(|(name, _)| name == key).map(|(_, value)| *value);
+		let kind = match header.get("XTENSION").map(|name| name.into_string()) {
+			Some(Ok(name)) => name.trim().to_string(),
+			_ if at == 0 => String::from("PRIMARY"),
+			_ => { return Err(Err::file(String::from("is not a valid fits file: an extension has no XTENSION"), path)); }
+		};
+
+		// |BITPIX| * GCOUNT * (PCOUNT + NAXIS1 * ... * NAXISn) bits, random groups have NAXIS1 = 0
+		let naxis = exact("NAXIS").unwrap_or(0);
+		if naxis > MAX_NAXIS { return Err(Err::file(format!("is not a valid fits file: NAXIS is over {}", MAX_NAXIS), path)); }
+		let groups = header.get("GROUPS") == Some(&Node::boolean(true)) && exact("NAXIS1") == Some(0);
+		let pixels = if naxis == 0 { Some(0) }else { (if groups { 2 }else { 1 }..=naxis).try_fold(1usize, |total, axis| total.checked_mul(exact(&format!("NAXIS{}", axis)).unwrap_or(0))) };
+		let bits = header.number("BITPIX").unwrap_or(8.0).abs() as usize;
+		let size = pixels.and_then(|pixels| pixels.checked_add(exact("PCOUNT").unwrap_or(0))).and_then(|count| count.checked_mul(bits / 8)?.checked_mul(exact("GCOUNT").unwrap_or(1)));
+		let data = size.and_then(|size| bytes.get(start..start.checked_add(size)?)).ok_or_else(|| Err::file(String::from("is truncated, an HDU is shorter than its header describes"), path))?;
+
+		let mut fields = vec![(String::from("type"), Node::string(kind.clone()))];
+		match kind.as_str() {
+			"PRIMARY" | "IMAGE" if !groups => { fields.push((String::from("data"), read_image(&header, &integers, data, path)?)); },
+			"BINTABLE" => {
+				let (names, columns) = read_table(&header, &integers, data, path)?;
+				fields.push((String::from("names"), names));
+				fields.push((String::from("data"), columns));
+			},
+			_ => { fields.push((String::from("data"), Node::void())); } // ascii tables and random groups
+		}
+		fields.insert(1, (String::from("header"), Node::instance("Header", header.cards)));
+		hdus.push(Node::instance("Hdu", fields));
+		at = start + data.len().div_ceil(BLOCK) * BLOCK;
+	}
+	Ok(Node::list(hdus))
+}
+
+
+// ---- writing
+
+
+fn card<'a> (key: &str, value: &Node) -> Result<String, Err<'a>> {
+	let too_long = || Err::new(ErrorClass::CustomError(format!("The value of the fits keyword {} doesn't fit on one card", key)), value.clone());
+	let text = match *value.signature {
+		NodeClass::Void => format!("{:<8}=", key),
+		NodeClass::Boolean(b) => format!("{:<8}= {:>20}", key, if b { "T" }else { "F" }),
+		NodeClass::Number(num) if num.fract() == 0.0 && num.abs() < 1e15 => format!("{:<8}= {:>20}", key, num as i64),
+		NodeClass::Number(num) if num.is_finite() => format!("{:<8}= {:>20}", key, format!("{:E}", num)),
+		NodeClass::String(ref text) => format!("{:<8}= '{:<8}'", key, text.replace('\'', "''")),
+		_ => { return Err(Err::new(ErrorClass::Conversion(value.decode(), value.get_type(), "fits keyword value"), value.clone())); }
+	};
+	if text.len() > CARD { return Err(too_long()); }
+	Ok(format!("{:<80}", text))
+}
+
+
+fn header_cards<'a> (header: Option<&Node>) -> Result<Vec<(String, Node)>, Err<'a>> {
+	let header = match header {
+		None => { return Ok(Vec::new()); },
+		Some(node) if node.get_type() == "Void" => { return Ok(Vec::new()); },
+		Some(node) if node.get_type() == "ObjectInstance" && node.branches.len() == 2 => node,
+		Some(node) => { return Err(Err::new(ErrorClass::TypeMismatch("ObjectInstance", node.get_type()), node.clone())); }
+	};
+	let mut cards = Vec::new();
+	for (key, value) in header.branches[0].branches.iter().zip(header.branches[1].branches.iter()) {
+		let key = key.id().0.to_string();
+		if value.id().0 == "<extern_link>" { continue; }
+		if key.len() > 8 || key.is_empty() || !key.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-' || c == '_') {
+			return Err(Err::new(ErrorClass::CustomError(format!("'{}' is not a valid fits keyword, keywords are up to 8 upper case letters, digits, - or _", key)), value.clone()));
+		}
+		cards.push((key, value.clone()));
+	}
+	Ok(cards)
+}
+
+
+fn write_image<'a> (out: &mut Vec<u8>, image: &Node, header: Option<&Node>, primary: bool) -> Result<(), Err<'a>> {
+	let (shape, pixels) = match image.get_type() {
+		"Void" => (Vec::new(), Vec::new()),
+		_ => {
+			let (shape, pixels) = flatten_array(&image.validate_type("List")?)?;
+			if shape.contains(&0) { (Vec::new(), Vec::new()) }else { (shape, pixels) }
+	}};
+	let user = header_cards(header)?;
+	let number = |key: &str| user.iter().find(|(name, _)| name == key).and_then(|(_, value)| value.into_number().ok()).map(|num| num as f64);
+	let (kind, bitpix) = match number("BITPIX") {
+		None | Some(-32.0) => (b'E', -32), // exact for kylu numbers
+		Some(-64.0) => (b'D', -64),
+		Some(8.0) => (b'B', 8),
+		Some(16.0) => (b'I', 16),
+		Some(32.0) => (b'J', 32),
+		Some(64.0) => (b'K', 64),
+		Some(other) => { return Err(Err::new(ErrorClass::CustomError(format!("{} is not a valid BITPIX, expected 8, 16, 32, 64, -32 or -64", other)), Node::number(other as f32))); }
+	};
+
+	let mut cards = vec![if primary { card("SIMPLE", &Node::boolean(true))? }else { card("XTENSION", &Node::string(String::from("IMAGE")))? }];
+	cards.push(card("BITPIX", &Node::number(bitpix as f32))?);
+	cards.push(card("NAXIS", &Node::number(shape.len() as f32))?);
+	for (axis, len) in shape.iter().rev().enumerate() { cards.push(card(&format!("NAXIS{}", axis + 1), &Node::number(*len as f32))?); }
+	if primary { cards.push(card("EXTEND", &Node::boolean(true))?); }
+	else {
+		cards.push(card("PCOUNT", &Node::number(0.0))?);
+		cards.push(card("GCOUNT", &Node::number(1.0))?);
+	}
+	for (key, value) in user.iter() {
+		let structural = STRUCTURAL.contains(&key.as_str()) || (key.starts_with("NAXIS") && key[5..].chars().all(|c| c.is_ascii_digit()));
+		if structural || key == "END" { continue; }
+		match (key.as_str(), value.get_type()) {
+			("COMMENT" | "HISTORY", "List") => {
+				for line in value.branches[0].branches.iter() {
+					let text = line.into_string()?;
+					if text.len() > CARD - 8 { return Err(Err::new(ErrorClass::CustomError(format!("{} cards hold at most 72 characters", key)), line.clone())); }
+					cards.push(format!("{:<8}{:<72}", key, text));
+			}},
+			_ => { cards.push(card(key, value)?); }
+	}}
+	cards.push(format!("{:<80}", "END"));
+	let header_text = cards.concat();
+	out.extend_from_slice(header_text.as_bytes());
+	out.resize(out.len().div_ceil(BLOCK) * BLOCK, b' ');
+
+	// pixels are stored as (value - BZERO) / BSCALE, Void is BLANK in integer images and NaN in float ones
+	let (scale, zero) = (number("BSCALE").unwrap_or(1.0), number("BZERO").unwrap_or(0.0));
+	let blank = number("BLANK");
+	let integer = bitpix > 0;
+	for pixel in pixels.iter() {
+		let raw = match *pixel.signature {
+			NodeClass::Void if integer => blank.ok_or_else(|| Err::new(ErrorClass::CustomError(String::from("Void pixels in an integer image need a BLANK keyword")), pixel.clone()))?,
+			NodeClass::Void => f64::NAN,
+			_ => (pixel.into_number()? as f64 - zero) / scale
+		};
+		let raw = if integer { raw.round() }else { raw };
+		let (min, max) = match bitpix {
+			8 => (0.0, 255.0),
+			16 => (i16::MIN as f64, i16::MAX as f64),
+			32 => (i32::MIN as f64, i32::MAX as f64),
+			64 => (i64::MIN as f64, i64::MAX as f64),
+			_ => (f64::NEG_INFINITY, f64::INFINITY)
+		};
+		if integer && !(min..=max).contains(&raw) { return Err(Err::new(ErrorClass::Conversion(pixel.decode(), "Number", "fits integer pixel"), pixel.clone())); }
+		match kind {
+			b'B' => out.push(raw as u8),
+			b'I' => out.extend_from_slice(&(raw as i16).to_be_bytes()),
+			b'J' => out.extend_from_slice(&(raw as i32).to_be_bytes()),
+			b'K' => out.extend_from_slice(&(raw as i64).to_be_bytes()),
+			b'E' => out.extend_from_slice(&(raw as f32).to_be_bytes()),
+			_ => out.extend_from_slice(&raw.to_be_bytes())
+		}
+	}
+	out.resize(out.len().div_ceil(BLOCK) * BLOCK, 0);
+	Ok(())
+}
+
+
+// data is an image (nested list) written with the header instance, or a list of Hdu instances written in order
+pub fn write_fits<'a> (path: &Node, data: &Node, header: Option<&Node>) -> Result<Node, Err<'a>> {
+	let mut out = Vec::new();
+	let is_hdus = data.get_type() == "List" && data.branches[0].branches.first().is_some_and(|first| first.get_type() == "ObjectInstance");
+	if is_hdus {
+		for (index, hdu) in data.branches[0].branches.iter().enumerate() {
+			let fields = NameSpace::from(hdu.validate_type("ObjectInstance")?.branches[0].clone(), hdu.branches[1].clone())?;
+			let field = |name: &str| fields.get(Node::symbol(name.to_string())).ok();
+			if let Some(kind) = field("type") {
+				if !["PRIMARY", "IMAGE"].contains(&kind.into_string()?.as_str()) {
+					return Err(Err::new(ErrorClass::CustomError(format!("write_fits only writes image HDUs, found a {} HDU", kind.decode())), hdu.clone()));
+			}}
+			write_image(&mut out, &field("data").unwrap_or_else(Node::void), field("header").as_ref(), index == 0)?;
+		}
+	}else { write_image(&mut out, data, header, true)?; }
+
+	match fs::write(path.into_string()?, out) {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be written: {}", err), path))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::{ env, process };
+	use super::*;
+	
+	// one HDU, cards are (keyword, value as written in the card)
+	fn hdu (cards: &[(&str, &str)], data: &[u8]) -> Vec<u8> {
+		let mut out: Vec<u8> = cards.iter().flat_map(|(key, value)| format!("{:<80}", format!("{:<8}= {:>20}", key, value)).into_bytes()).collect();
+		out.extend_from_slice(format!("{:<80}", "END").as_bytes());
+		out.resize(out.len().div_ceil(BLOCK) * BLOCK, b' ');
+		out.extend_from_slice(data);
+		out.resize(out.len().div_ceil(BLOCK) * BLOCK, 0);
+		out
+	}
+	
+	fn table (cards: &[(&str, &str)], data: &[u8]) -> Vec<u8> {
+		let mut bytes = hdu(&[("SIMPLE", "T"), ("BITPIX", "8"), ("NAXIS", "0")], &[]);
+		let mut extension = vec![("XTENSION", "'BINTABLE'"), ("BITPIX", "8"), ("NAXIS", "2")];
+		extension.extend_from_slice(cards);
+		bytes.extend(hdu(&extension, data));
+		bytes
+	}
+	
+	fn path (name: &str) -> Node { Node::string(env::temp_dir().join(format!("kylu-{}-{}.fits", name, process::id())).to_string_lossy().to_string()) }
+	
+	// the data field of each HDU, or the error's message
+	fn read (name: &str, bytes: &[u8]) -> Result<Vec<Node>, String> {
+		let path = path(name);
+		fs::write(path.into_string().unwrap(), bytes).unwrap();
+		let result = read_fits(&path).map_err(|err| err.message());
+		fs::remove_file(path.into_string().unwrap()).unwrap();
+		let hdus = result?;
+		Ok(hdus.branches[0].branches.iter().map(|hdu| NameSpace::from(hdu.branches[0].clone(), hdu.branches[1].clone()).unwrap().get(Node::symbol(String::from("data"))).unwrap()).collect())
+	}
+	
+	fn numbers (values: &[f32]) -> Node { Node::list(values.iter().map(|num| Node::number(*num)).collect()) }
+	
+	#[test]
+	fn image_round_trip () {
+		let image = Node::list(vec![numbers(&[1.0, 2.0, 3.0]), numbers(&[4.0, -5.0, 6.5])]);
+		let header = Node::instance("Header", vec![(String::from("OBJECT"), Node::string(String::from("M31"))), (String::from("COMMENT"), Node::list(vec![Node::string(String::from("first")), Node::string(String::from("second"))]))]);
+		let file = path("image");
+		write_fits(&file, &image, Some(&header)).unwrap();
+		let hdus = read_fits(&file).unwrap();
+		fs::remove_file(file.into_string().unwrap()).unwrap();
+		
+		let hdu = NameSpace::from(hdus.branches[0].branches[0].branches[0].clone(), hdus.branches[0].branches[0].branches[1].clone()).unwrap();
+		assert!(hdu.get(Node::symbol(String::from("data"))).unwrap() == image);
+		let header = hdu.get(Node::symbol(String::from("header"))).unwrap();
+		let header = NameSpace::from(header.branches[0].clone(), header.branches[1].clone()).unwrap();
+		let key = |name: &str| header.get(Node::symbol(name.to_string())).unwrap().decode();
+		assert_eq!((key("NAXIS1"), key("NAXIS2"), key("OBJECT"), key("COMMENT")), (String::from("3"), String::from("2"), String::from("M31"), String::from("[first, second]")));
+	}
+	
+	#[test]
+	fn scaled_integers () {
+		let data: Vec<u8> = [10i16, -1, 30].iter().flat_map(|num| num.to_be_bytes()).collect();
+		let bytes = hdu(&[("SIMPLE", "T"), ("BITPIX", "16"), ("NAXIS", "1"), ("NAXIS1", "3"), ("BSCALE", "0.5"), ("BZERO", "1"), ("BLANK", "-1")], &data);
+		assert!(read("scaled", &bytes).unwrap()[0] == Node::list(vec![Node::number(6.0), Node::void(), Node::number(16.0)]));
+	}
+	
+	#[test]
+	fn binary_table () {
+		let mut data = Vec::new();
+		for (id, name, heap) in [(1i32, "abc", 0i32), (2, "de\0", 2)] {
+			data.extend_from_slice(&id.to_be_bytes());
+			data.extend_from_slice(name.as_bytes());
+			data.extend_from_slice(&2i32.to_be_bytes()); // two heap elements
+			data.extend_from_slice(&(heap * 2).to_be_bytes());
+		}
+		data.extend([7i16, 8, 9, 10].iter().flat_map(|num| num.to_be_bytes()));
+		let bytes = table(&[("NAXIS1", "15"), ("NAXIS2", "2"), ("PCOUNT", "8"), ("GCOUNT", "1"), ("TFIELDS", "3"),
+			("TFORM1", "'1J'"), ("TTYPE1", "'id'"), ("TFORM2", "'3A'"), ("TFORM3", "'1PI(2)'")], &data);
+		let columns = read("table", &bytes).unwrap();
+		assert_eq!(columns[1].decode(), "[[1, 2], [abc, de], [[7, 8], [9, 10]]]");
+	}
+	
+	// headers that used to panic, overflow or allocate without bound
+	#[test]
+	fn malformed_headers () {
+		let primary = |cards: &[(&str, &str)]| hdu(&[&[("SIMPLE", "T")][..], cards].concat(), &[]);
+		let huge = u64::MAX.to_string();
+		let cases: Vec<(&str, Vec<u8>)> = vec![
+			("naxis", primary(&[("BITPIX", "8"), ("NAXIS", "200000000")])),
+			("axes", primary(&[("BITPIX", "8"), ("NAXIS", "2"), ("NAXIS1", "4294967296"), ("NAXIS2", "4294967296")])),
+			("gcount", primary(&[("BITPIX", "64"), ("NAXIS", "1"), ("NAXIS1", "1"), ("GCOUNT", "9223372036854775807")])),
+			("rows", table(&[("NAXIS1", "0"), ("NAXIS2", "1000000000000"), ("TFIELDS", "1"), ("TFORM1", "'0J'")], &[])),
+			("tform", table(&[("NAXIS1", "8"), ("NAXIS2", "1"), ("TFIELDS", "2"), ("TFORM1", "'1J'"), ("TFORM2", "'4611686018427387904K'")], &[0; 8])),
+			("theap", table(&[("NAXIS1", "8"), ("NAXIS2", "1"), ("PCOUNT", "8"), ("THEAP", "9223372036854775807"), ("TFIELDS", "1"), ("TFORM1", "'1PJ'")], &[0, 0, 0, 1, 0, 0, 0, 0])),
+			("truncated", primary(&[("BITPIX", "-32"), ("NAXIS", "1"), ("NAXIS1", "100000")])),
+			("end", b"SIMPLE  =                    T".to_vec())
+		];
+		for (name, bytes) in cases.iter() {
+			let thread = std::thread::Builder::new().name(name.to_string());
+			let (file, bytes) = (format!("malformed-{}", name), bytes.clone());
+			let result = thread.spawn(move || read(&file, &bytes)).unwrap().join();
+			assert!(matches!(result, Ok(Err(_))), "the {} case was read or panicked: {:?}", name, result.map(|read| read.map(|hdus| hdus.iter().map(|hdu| hdu.decode()).collect::<Vec<String>>())));
+		}
+		// no groups means no data however large the axes are
+		let groups = read("groups", &primary(&[("BITPIX", "8"), ("NAXIS", "2"), ("NAXIS1", "0"), ("NAXIS2", &huge), ("GROUPS", "T"), ("GCOUNT", "0")]));
+		assert!(groups.unwrap()[0] == Node::void());
+	}
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use crate::utils::node::{ NodeClass, Node };
+use crate::utils::error::{ ErrorClass, Err, json_string };
+
+
+/*
+json objects become Json instances whose fields are the object's keys, arrays are lists, numbers are Numbers (f32,
+so integers past 2^24 lose precision) and null is Void. any object instance is written back as an object, so values
+survive a round trip except for the instance's name. functions, symbols and non finite numbers have no json form
+*/
+const MAX_DEPTH: usize = 10000; // nesting past this is rejected instead of overflowing the stack
+
+
+struct Reader<'s> {
+	text: &'s str,
+	pos: usize // byte offset
+}
+
+impl <'s> Reader<'s> {
+	fn peek (&self) -> Option<u8> { self.text.as_bytes().get(self.pos).copied() }
+
+	fn skip_space (&mut self) {
+		while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() { self.pos += 1; }
+	}
+
+	// line and column of the current position for error messages
+	fn fail<'a> (&self, expected: &str) -> Err<'a> {
+		let before = &self.text[..self.pos];
+		let line = before.matches('\n').count() + 1;
+		let col = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+		let found = match self.text[self.pos..].chars().next() {
+			Some(c) => format!("'{}'", c),
+			None => String::from("the end of the text")
+		};
+		Err::new(ErrorClass::CustomError(format!("Invalid JSON at line {}, column {}: expected {}, found {}", line, col, expected, found)), Node::void())
+	}
+
+	fn expect<'a> (&mut self, token: &str, expected: &str) -> Result<(), Err<'a>> {
+		if self.text[self.pos..].starts_with(token) {
+			self.pos += token.len();
+			Ok(())
+		}else { Err(self.fail(expected)) }
+	}
+
+	fn value<'a> (&mut self, depth: usize) -> Result<Node, Err<'a>> {
+		if depth > MAX_DEPTH { return Err(self.fail("fewer nested arrays and objects")); }
+		self.skip_space();
+		match self.peek() {
+			Some(b'{') => self.object(depth),
+			Some(b'[') => self.array(depth),
+			Some(b'"') => Ok(Node::string(self.string()?)),
+			Some(b't') => { self.expect("true", "a value")?; Ok(Node::boolean(true)) },
+			Some(b'f') => { self.expect("false", "a value")?; Ok(Node::boolean(false)) },
+			Some(b'n') => { self.expect("null", "a value")?; Ok(Node::void()) },
+			Some(b'-' | b'0'..=b'9') => self.number(),
+			_ => Err(self.fail("a value"))
+		}
+	}
+
+	fn number<'a> (&mut self) -> Result<Node, Err<'a>> {
+		let start = self.pos;
+		let digits = |reader: &mut Reader| {
+			let from = reader.pos;
+			while let Some(b'0'..=b'9') = reader.peek() { reader.pos += 1; }
+			reader.pos > from
+		};
+		if self.peek() == Some(b'-') { self.pos += 1; }
+		if self.peek() == Some(b'0') { self.pos += 1; }
+		else if !digits(self) { return Err(self.fail("a digit")); }
+		if self.peek() == Some(b'.') {
+			self.pos += 1;
+			if !digits(self) { return Err(self.fail("a digit after the decimal point")); }
+		}
+		if let Some(b'e' | b'E') = self.peek() {
+			self.pos += 1;
+			if let Some(b'+' | b'-') = self.peek() { self.pos += 1; }
+			if !digits(self) { return Err(self.fail("a digit in the exponent")); }
+		}
+		Ok(Node::number(self.text[start..self.pos].parse::<f32>().unwrap()))
+	}
+
+	fn hex_escape<'a> (&mut self) -> Result<u32, Err<'a>> {
+		let code = self.text.get(self.pos..self.pos + 4).and_then(|hex| u32::from_str_radix(hex, 16).ok());
+		match code {
+			Some(code) => {
+				self.pos += 4;
+				Ok(code)
+			},
+			None => Err(self.fail("four hex digits"))
+		}
+	}
+
+	fn string<'a> (&mut self) -> Result<String, Err<'a>> {
+		self.pos += 1; // opening quote
+		let mut out = String::new();
+		loop {
+			let c = match self.text[self.pos..].chars().next() {
+				Some(c) => c,
+				None => { return Err(self.fail("a closing quote")); }
+			};
+			match c {
+				'"' => {
+					self.pos += 1;
+					return Ok(out);
+				},
+				'\\' => {
+					self.pos += 1;
+					let escaped = match self.peek() {
+						Some(b'"') => '"',
+						Some(b'\\') => '\\',
+						Some(b'/') => '/',
+						Some(b'b') => '\u{8}',
+						Some(b'f') => '\u{c}',
+						Some(b'n') => '\n',
+						Some(b'r') => '\r',
+						Some(b't') => '\t',
+						Some(b'u') => {
+							self.pos += 1;
+							let mut code = self.hex_escape()?;
+							// characters outside the basic plane are written as a surrogate pair
+							if (0xd800..0xdc00).contains(&code) && self.text[self.pos..].starts_with("\\u") {
+								let rewind = self.pos;
+								self.pos += 2;
+								let low = self.hex_escape()?;
+								if (0xdc00..0xe000).contains(&low) { code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00); }
+								else { self.pos = rewind; }
+							}
+							out.push(char::from_u32(code).unwrap_or('\u{fffd}')); // lone surrogates can't be held by a String
+							continue;
+						},
+						_ => { return Err(self.fail("an escape sequence")); }
+					};
+					self.pos += 1;
+					out.push(escaped);
+				},
+				c if (c as u32) < 0x20 => { return Err(self.fail("an escaped control character")); },
+				c => {
+					self.pos += c.len_utf8();
+					out.push(c);
+			}}
+		}
+	}
+
+	fn array<'a> (&mut self, depth: usize) -> Result<Node, Err<'a>> {
+		self.pos += 1;
+		let mut items = Vec::new();
+		self.skip_space();
+		if self.peek() == Some(b']') {
+			self.pos += 1;
+			return Ok(Node::list(items));
+		}
+		loop {
+			items.push(self.value(depth + 1)?);
+			self.skip_space();
+			match self.peek() {
+				Some(b',') => { self.pos += 1; },
+				Some(b']') => {
+					self.pos += 1;
+					return Ok(Node::list(items));
+				},
+				_ => { return Err(self.fail("',' or ']'")); }
+		}}
+	}
+
+	// a repeated key keeps its last value like most json readers
+	fn object<'a> (&mut self, depth: usize) -> Result<Node, Err<'a>> {
+		self.pos += 1;
+		let mut fields: Vec<(String, Node)> = Vec::new();
+		let mut seen: HashMap<String, usize> = HashMap::new(); // index of each key in fields
+		self.skip_space();
+		if self.peek() == Some(b'}') { self.pos += 1; }
+		else { loop {
+			self.skip_space();
+			if self.peek() != Some(b'"') { return Err(self.fail("a string key")); }
+			let key = self.string()?;
+			self.skip_space();
+			self.expect(":", "':' after the key")?;
+			let value = self.value(depth + 1)?;
+			match seen.get(&key) {
+				Some(index) => { fields[*index].1 = value; },
+				None => {
+					seen.insert(key.clone(), fields.len());
+					fields.push((key, value));
+			}}
+			self.skip_space();
+			match self.peek() {
+				Some(b',') => { self.pos += 1; },
+				Some(b'}') => {
+					self.pos += 1;
+					break;
+				},
+				_ => { return Err(self.fail("',' or '}'")); }
+		}}}
+		Ok(Node::instance("Json", fields))
+	}
+}
+
+
+pub fn from_json<'a> (text: &str) -> Result<Node, Err<'a>> {
+	let mut reader = Reader { text, pos: 0 };
+	let value = reader.value(0)?;
+	reader.skip_space();
+	if reader.pos != text.len() { return Err(reader.fail("the end of the text")); }
+	Ok(value)
+}
+
+
+fn unsupported<'a> (value: &Node) -> Err<'a> {
+	let shown = if value.get_type() == "Object" { String::from("<function>") }else { value.decode() }; // don't print the whole body
+	Err::new(ErrorClass::Conversion(shown, value.get_type(), "JSON"), value.clone())
+}
+
+
+// indent is Some(level) when pretty printing
+fn encode<'a> (out: &mut String, value: &Node, indent: Option<usize>, depth: usize) -> Result<(), Err<'a>> {
+	if depth > MAX_DEPTH { return Err(unsupported(value)); }
+	let items: Vec<(Option<String>, &Node)> = match *value.signature {
+		NodeClass::Void => { out.push_str("null"); return Ok(()); },
+		NodeClass::Boolean(b) => { out.push_str(if b { "true" }else { "false" }); return Ok(()); },
+		NodeClass::String(ref text) => { out.push_str(&json_string(text)); return Ok(()); },
+		NodeClass::Number(num) if num.is_finite() => { out.push_str(&format!("{}", num)); return Ok(()); },
+		NodeClass::List => value.branches[0].branches.iter().map(|item| (None, item)).collect(),
+		NodeClass::ObjectInst(ref name) if name != "<extern_link>" && value.branches.len() == 2 => {
+			value.branches[0].branches.iter().zip(value.branches[1].branches.iter())
+				.filter(|(_, item)| item.id().0 != "<extern_link>") // the [@] link instances keep to their defining scope
+				.map(|(key, item)| (Some(key.id().0.to_string()), item)).collect()
+		},
+		_ => { return Err(unsupported(value)); }
+	};
+	let (open, close) = if value.get_type() == "List" { ('[', ']') }else { ('{', '}') };
+	out.push(open);
+	for (index, (key, item)) in items.iter().enumerate() {
+		if index > 0 { out.push(','); }
+		if let Some(level) = indent {
+			out.push('\n');
+			out.push_str(&"  ".repeat(level + 1));
+		}
+		if let Some(key) = key {
+			out.push_str(&json_string(key));
+			out.push_str(if indent.is_some() { ": " }else { ":" });
+		}
+		encode(out, item, indent.map(|level| level + 1), depth + 1)?;
+	}
+	if let (Some(level), false) = (indent, items.is_empty()) {
+		out.push('\n');
+		out.push_str(&"  ".repeat(level));
+	}
+	out.push(close);
+	Ok(())
+}
+
+
+pub fn to_json<'a> (value: &Node, pretty: bool) -> Result<Node, Err<'a>> {
+	let mut out = String::new();
+	encode(&mut out, value, if pretty { Some(0) }else { None }, 0)?;
+	Ok(Node::string(out))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+	use super::*;
+	
+	// the parsed value written back without spacing
+	fn parsed (text: &str) -> Option<String> { from_json(text).ok().map(|value| to_json(&value, false).unwrap().into_string().unwrap()) }
+	
+	fn string (text: &str) -> String { from_json(text).unwrap().into_string().unwrap() }
+	
+	#[test]
+	fn escapes () {
+		assert_eq!(string(r#""a\"b\\c\/d\b\f\n\r\t""#), "a\"b\\c/d\u{8}\u{c}\n\r\t");
+		assert_eq!(string(r#""é中""#), "é中");
+		assert!(from_json(r#""\x""#).is_err());
+		assert!(from_json(r#""\u12""#).is_err());
+		assert!(from_json("\"tab\there\"").is_err()); // control characters have to be escaped
+		assert!(from_json("\"open").is_err());
+		
+		let text = "quote \" slash \\ line\n tab\t bell\u{7} é";
+		assert_eq!(from_json(&to_json(&Node::string(text.to_string()), false).unwrap().into_string().unwrap()).unwrap().into_string().unwrap(), text);
+	}
+	
+	#[test]
+	fn surrogate_pairs () {
+		assert_eq!(string(r#""\ud83d\ude00""#), "😀");
+		assert_eq!(string(r#""\uD834\uDD1E \u00e9""#), "𝄞 é");
+		// halves without their partner can't be held by a String
+		assert_eq!(string(r#""\ud83d""#), "\u{fffd}");
+		assert_eq!(string(r#""\ude00x""#), "\u{fffd}x");
+		assert_eq!(string(r#""\ud83dA""#), "\u{fffd}A");
+		assert!(from_json(r#""\ud83d\u00""#).is_err());
+	}
+	
+	#[test]
+	fn values () {
+		assert_eq!(parsed(" [1, -2.5e1, true, false, null, \"s\", [], {}] "), Some(String::from(r#"[1,-25,true,false,null,"s",[],{}]"#)));
+		assert_eq!(parsed("[1,]"), None);
+		assert_eq!(parsed("01"), None);
+		assert_eq!(parsed("1 2"), None);
+		// the interpreter thread's stack, nesting up to the limit doesn't fit in a test thread's
+		let deep = thread::Builder::new().stack_size(256 * 1024 * 1024).spawn(|| {
+			let nest = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+			(parsed(&nest(MAX_DEPTH)).is_some(), parsed(&nest(MAX_DEPTH + 2)).is_none())
+		}).unwrap().join().unwrap();
+		assert_eq!(deep, (true, true));
+		
+		// a repeated key keeps its place and takes the last value
+		let object = from_json(r#"{"a": 1, "b": 2, "a": 3}"#).unwrap();
+		assert_eq!(to_json(&object, false).unwrap().into_string().unwrap(), r#"{"a":3,"b":2}"#);
+		assert_eq!(to_json(&object, true).unwrap().into_string().unwrap(), "{\n  \"a\": 3,\n  \"b\": 2\n}");
+	}
+}
@@ -1,3 +1,9 @@
 pub mod error;
 pub mod node;
 pub mod builtin_funcs;
+pub mod terminal;
+pub mod workspace;
+pub mod csv;
+pub mod json;
+pub mod npy;
+pub mod fits;
@@ -1,4 +1,26 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{ Arc, OnceLock, RwLock };
 use crate::utils::error::{ ErrorClass, Err };
+use crate::compiler::Chunk;
+
+
+// symbol names are interned so namespaces can hash one integer instead of comparing strings
+static SYMBOLS: OnceLock<RwLock<HashMap<String, usize>>> = OnceLock::new();
+
+pub fn intern (name: &str) -> usize {
+	let symbols = SYMBOLS.get_or_init(|| RwLock::new(HashMap::new()));
+	if let Some(id) = symbols.read().unwrap().get(name) { return *id; }
+	let mut writer = symbols.write().unwrap();
+	let id = writer.len();
+	*writer.entry(name.to_string()).or_insert(id)
+}
+
+
+// nodes alive on this thread, the memory limit is measured against it
+thread_local! { static LIVE: Cell<usize> = const { Cell::new(0) }; }
+
+pub fn live_nodes () -> usize { LIVE.with(|live| live.get()) }
 
 
 // must be valid after the source that created them is out of scope
@@ -10,6 +32,7 @@ pub enum NodeClass {
     Number(f32), // integer 
     Boolean(bool),
     Symbol(String),
+    Local(String, usize), // symbol the parser resolved to a namespace slot
     Oper(String, u8), // u8 operator precedeb
     Misc(String),
     Void,
@@ -25,31 +48,81 @@ pub enum NodeClass {
     Combinator,
     Call,
     If,
+    Try,
+    Import,
+    Export,
+    Code(Arc<Chunk>), // compiled body of an object literal
 }
 
 
+// nodes are immutable and shared, so cloning one (or passing it to a function) only bumps reference counts
+// anything that changes a node's contents goes through branches_mut(), which copies them first if they're shared
 #[derive(Debug, Clone)]
 pub struct Node {
-    pub signature: Box<NodeClass>,
+    pub signature: Arc<NodeClass>,
     pub span: [usize; 2],
-    pub branches: Vec<Box<Node>>
+    pub branches: Arc<Vec<Node>>
 }
 
+// compared with a work list instead of recursion, lists can be nested deeper than the stack allows
 impl PartialEq for Node {
 	fn eq(&self, other: &Node) -> bool {
-		if self.signature != other.signature { return false; }
-		if self.branches != other.branches { return false; }
+		let mut pending = vec![(self, other)];
+		while let Some((a, b)) = pending.pop() {
+			if !Arc::ptr_eq(&a.signature, &b.signature) && a.signature != b.signature { return false; }
+			if Arc::ptr_eq(&a.branches, &b.branches) { continue; }
+			if a.branches.len() != b.branches.len() { return false; }
+			pending.extend(a.branches.iter().zip(b.branches.iter()));
+		}
 		return true;
 	}
 }
 
+// what's left to write while show() and decode() walk a tree
+enum Piece<'n> {
+	Node(&'n Node),
+	Text(&'static str)
+}
+
+// shared by every node without branches so primitives don't allocate an empty list
+static LEAF: OnceLock<Arc<Vec<Node>>> = OnceLock::new();
+
+fn leaf () -> Arc<Vec<Node>> { LEAF.get_or_init(|| Arc::new(Vec::new())).clone() }
+
+// lists can be nested arbitrarily deep at runtime, so the last owner of a tree takes it apart
+// with a loop instead of letting each level drop the next one recursively
+impl Drop for Node {
+	fn drop (&mut self) {
+		if Arc::strong_count(&self.signature) == 1 { LIVE.with(|live| live.set(live.get().saturating_sub(1))); }
+		if self.branches.is_empty() || Arc::strong_count(&self.branches) != 1 { return; }
+		let mut pending = vec![std::mem::replace(&mut self.branches, leaf())];
+		while let Some(branches) = pending.pop() {
+			if let Ok(mut nodes) = Arc::try_unwrap(branches) {
+				for node in nodes.iter_mut() {
+					if !node.branches.is_empty() { pending.push(std::mem::replace(&mut node.branches, leaf())); }
+			}}
+		}
+	}
+}
+
 impl Node {
     pub fn new (sign: NodeClass, span: [usize; 2], contents: Vec<Node>) -> Node {
-        Node { signature: Box::new(sign), span, branches: contents.into_iter().map(|node| Box::new(node)).collect() }
+    	let branches = match contents.len() {
+    		0 => leaf(),
+    		_ => Arc::new(contents)
+    	};
+    	LIVE.with(|live| live.set(live.get() + 1));
+        Node { signature: Arc::new(sign), span, branches }
     }
     
     // used when creating a new node from an existing node's contents
-    pub fn new_boxed (sign: NodeClass, span: [usize; 2], branches: Vec<Box<Node>>) -> Node { Node { signature: Box::new(sign), span, branches } }
+    pub fn new_shared (sign: NodeClass, span: [usize; 2], branches: Arc<Vec<Node>>) -> Node {
+    	LIVE.with(|live| live.set(live.get() + 1));
+    	Node { signature: Arc::new(sign), span, branches }
+    }
+    
+    // copy on write access to the branches
+    pub fn branches_mut (&mut self) -> &mut Vec<Node> { Arc::make_mut(&mut self.branches) }
     
     // shortcuts for creating common node types
     pub fn void () -> Node { Node::new(NodeClass::Void, [0, 0], Vec::new()) }
@@ -62,6 +135,14 @@ impl Node {
     
     pub fn boolean (b: bool) -> Node { Node::new(NodeClass::Boolean(b), [0, 0], Vec::new()) }
     
+    pub fn list (items: Vec<Node>) -> Node { Node::new(NodeClass::List, [0, 0], vec![Node::new(NodeClass::Field, [0, 0], items)]) }
+    
+    // object instance holding the fields in order, like the ones [!] returns
+    pub fn instance (name: &str, fields: Vec<(String, Node)>) -> Node {
+    	let (keys, values): (Vec<Node>, Vec<Node>) = fields.into_iter().map(|(key, value)| (Node::symbol(key), value)).unzip();
+    	Node::new(NodeClass::ObjectInst(name.to_string()), [0, 0], vec![Node::new(NodeClass::Field, [0, 0], keys), Node::new(NodeClass::Field, [0, 0], values)])
+    }
+    
     // unwrap node to inner value 
     pub fn into_number<'a> (&self) -> Result<f32, Err<'a>> {
     	match *self.signature {
@@ -79,9 +160,17 @@ impl Node {
     	match *self.signature {
     		NodeClass::String(ref strg) => Ok(strg.to_string()),
     		NodeClass::Symbol(ref strg) => Ok(strg.to_string()),
+    		NodeClass::Local(ref strg, _) => Ok(strg.to_string()),
     		_ => Err(Err::new(ErrorClass::TypeMismatch("String", self.get_type()), self.clone()))
     }}
     
+    // interned name used as a namespace key
+    pub fn key_id (&self) -> usize {
+    	match *self.signature {
+    		NodeClass::Symbol(ref name) | NodeClass::Local(ref name, _) => intern(name),
+    		_ => intern(&self.show())
+    }}
+    
     // commonly used node property checcks
     pub fn validate_type<'a> (&self, expected: &'a str) -> Result<Node, Err<'a>> {
 		if self.get_type() == expected { Ok(self.clone()) }else { Err(Err::new(ErrorClass::TypeMismatch(expected, self.get_type()), self.clone())) }
@@ -93,47 +182,91 @@ impl Node {
     
     // default representation for debugging
     pub fn show (&self) -> String {
-        format!("({}{})",
-            match *self.signature {
-                NodeClass::String(ref val) => format!("str: {}", val),
-                NodeClass::Number(ref val) => format!("num: {}", val),
-                NodeClass::Boolean(ref val) => format!("bool: {}", val),
-                NodeClass::Symbol(ref val) => format!("sym: {}", val),
-                NodeClass::Oper(ref val, _) => format!("oper: {}", val),
-                
-                NodeClass::OperExpr(ref val) => format!("opex: {}", val),
-                NodeClass::ObjectInst(ref val) => format!("inst: {}", val),
-                NodeClass::Field => String::from("[!]"),
-                NodeClass::List => String::from("[_]"),
-                NodeClass::Paren => String::from("(_)"),
-                NodeClass::Object => String::from("obj"),
-                NodeClass::Combinator => String::from("com"),
-                NodeClass::Call => String::from("call"),
-                NodeClass::If => String::from("ifs"),
-                NodeClass::Loop(ref t) => format!("loop: {}", t),
-                NodeClass::Misc(ref val) => String::from(val),
-                NodeClass::Void => String::from("void"),
-            },
-            if self.branches.len() != 0 { format!(" -->{}", self.branches.iter().map(|tok| tok.show()).collect::<String>()) }
-            else { String::new() }
-		)
+    	let mut out = String::new();
+    	let mut pending = vec![Piece::Node(self)];
+    	while let Some(piece) = pending.pop() {
+    		let node = match piece {
+    			Piece::Node(node) => node,
+    			Piece::Text(text) => {
+    				out.push_str(text);
+    				continue;
+    		}};
+    		out.push('(');
+    		out.push_str(&node.label());
+    		if !node.branches.is_empty() { out.push_str(" -->"); }
+    		pending.push(Piece::Text(")"));
+    		pending.extend(node.branches.iter().rev().map(Piece::Node));
+    	}
+    	out
+	}
+	
+	// the part of show() that describes the node itself
+	fn label (&self) -> String {
+        match *self.signature {
+            NodeClass::String(ref val) => format!("str: {}", val),
+            NodeClass::Number(ref val) => format!("num: {}", val),
+            NodeClass::Boolean(ref val) => format!("bool: {}", val),
+            NodeClass::Symbol(ref val) => format!("sym: {}", val),
+            NodeClass::Local(ref val, ref slot) => format!("loc: {}#{}", val, slot),
+            NodeClass::Oper(ref val, _) => format!("oper: {}", val),
+            
+            NodeClass::OperExpr(ref val) => format!("opex: {}", val),
+            NodeClass::ObjectInst(ref val) => format!("inst: {}", val),
+            NodeClass::Field => String::from("[!]"),
+            NodeClass::List => String::from("[_]"),
+            NodeClass::Paren => String::from("(_)"),
+            NodeClass::Object => String::from("obj"),
+            NodeClass::Combinator => String::from("com"),
+            NodeClass::Call => String::from("call"),
+            NodeClass::If => String::from("ifs"),
+            NodeClass::Try => String::from("try"),
+            NodeClass::Import => String::from("import"),
+            NodeClass::Export => String::from("export"),
+            NodeClass::Loop(ref t) => format!("loop: {}", t),
+            NodeClass::Code(_) => String::from("code"),
+            NodeClass::Misc(ref val) => String::from(val),
+            NodeClass::Void => String::from("void"),
+        }
 	}
 	
 	// used for terminal readout (write() function)
 	pub fn decode (&self) -> String {
-		match *self.signature {
-		    NodeClass::String(ref val) => String::from(val),
-		    NodeClass::Number(ref val) => format!("{}", val),
-		    NodeClass::Boolean(ref val) => format!("{}", val),
-		    NodeClass::Symbol(ref val) => String::from(val),
-		    NodeClass::Oper(ref val, _) => String::from(val),
-
-		    NodeClass::Field => format!("{}", self.branches.iter().map(|elem| elem.decode()).reduce(|total, elem| total + ", " + &elem).unwrap()),
-		    NodeClass::List => format!("[{}]", self.branches.iter().map(|elem| elem.decode()).reduce(|total, elem| total + ", " + &elem).unwrap()),
-		    NodeClass::Paren => format!("({})", self.branches.iter().map(|elem| elem.decode()).reduce(|total, elem| total + ", " + &elem).unwrap()),
-		    
-		    _ => self.show()
-	}}
+		let mut out = String::new();
+		let mut pending = vec![Piece::Node(self)];
+		while let Some(piece) = pending.pop() {
+			let node = match piece {
+				Piece::Node(node) => node,
+				Piece::Text(text) => {
+					out.push_str(text);
+					continue;
+			}};
+			let (open, close) = match *node.signature {
+			    NodeClass::String(ref val) | NodeClass::Symbol(ref val) | NodeClass::Local(ref val, _) | NodeClass::Oper(ref val, _) => { out.push_str(val); continue; },
+			    NodeClass::Number(ref val) => { out.push_str(&format!("{}", val)); continue; },
+			    NodeClass::Boolean(ref val) => { out.push_str(&format!("{}", val)); continue; },
+	
+			    NodeClass::Field => ("", ""),
+			    NodeClass::List => ("[", "]"),
+			    NodeClass::Paren => ("(", ")"),
+			    NodeClass::ObjectInst(ref name) if name == "Error" => { // kind and message fields
+			    	match node.branches.get(1) {
+			    		Some(values) if values.branches.len() >= 2 => { out.push_str(&format!("<Error {}: {}>", values.branches[0].decode(), values.branches[1].decode())); },
+			    		_ => { out.push_str(&node.show()); }
+			    	}
+			    	continue;
+			    },
+			    
+			    _ => { out.push_str(&node.show()); continue; }
+			};
+			out.push_str(open);
+			pending.push(Piece::Text(close));
+			for (index, elem) in node.branches.iter().enumerate().rev() {
+				pending.push(Piece::Node(elem));
+				if index > 0 { pending.push(Piece::Text(", ")); }
+			}
+		}
+		out
+	}
 	
 	// user interface to identify nodes (type() function)
 	pub fn get_type<'a> (&self) -> &'a str {
@@ -142,18 +275,27 @@ impl Node {
             NodeClass::Number(_) => "Number",
             NodeClass::Boolean(_) => "Boolean",
             NodeClass::Symbol(_) => "Symbol",
+            NodeClass::Local(_, _) => "Symbol",
             NodeClass::Oper(_, _) => "Operator",
             
             NodeClass::OperExpr(_) => "OperatorExpression",
+            NodeClass::ObjectInst(ref name) if name == "Error" => "Error", // made by the vm for caught errors
             NodeClass::ObjectInst(_) => "ObjectInstance",
             NodeClass::Field => "Field",
             NodeClass::List => "List",
-            NodeClass::Paren => self.branches[0].branches[0].get_type(), // get type of inner object
+            NodeClass::Paren => match self.branches.first().and_then(|inner| inner.branches.first()) { // get type of inner object
+            	Some(inner) => inner.get_type(),
+            	None => "Void"
+            },
             NodeClass::Object => "Object",
             NodeClass::Combinator => "Combinator",
             NodeClass::Call => "Call",
             NodeClass::If => "IfExpression",
+            NodeClass::Try => "TryExpression",
+            NodeClass::Import => "ImportStatement",
+            NodeClass::Export => "ExportStatement",
             NodeClass::Loop(_) => "LoopExpression",
+            NodeClass::Code(_) => "Code",
             NodeClass::Misc(_) => "MiscCharacter",
             NodeClass::Void => "Void",
 	}}
@@ -164,6 +306,7 @@ impl Node {
 		match *self.signature {
 			NodeClass::String(ref a) => (a, "String"),
             NodeClass::Symbol(ref a) => (a, "Symbol"),
+            NodeClass::Local(ref a, _) => (a, "Symbol"),
             NodeClass::Oper(ref a, _) => (a, "Operator"),
             
             NodeClass::OperExpr(ref a) => (a, "OperatorExpression"),
@@ -0,0 +1,717 @@
+use std::fs;
+use crate::utils::node::{ NodeClass, Node };
+use crate::utils::error::{ ErrorClass, Err };
+
+
+/*
+.npy files are a magic string, a version, a python dict literal header {'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }
+padded to a multiple of 64 bytes, and then the raw elements. arrays are read into nested lists in row major order whatever the
+file's order, numbers are f32 so float64 and 64 bit integers lose precision. .npz archives are zip files holding one .npy per
+array, entries may be stored or deflated (np.savez_compressed). arrays are written little endian in C order, archives uncompressed
+*/
+const MAGIC: &[u8] = b"\x93NUMPY";
+const ALIGNMENT: usize = 64;
+const MAX_DIMS: usize = 64; // numpy's own limit, also keeps nest and flatten from recursing deeper than this
+const MAX_EMPTY_LISTS: usize = 1 << 20; // an empty array like (100000000000, 0) holds no values but still nests into lists
+
+// dtype names accepted by save_npy and their descr strings
+const DTYPES: [(&str, &str); 11] = [
+	("float32", "<f4"), ("float64", "<f8"),
+	("int8", "|i1"), ("int16", "<i2"), ("int32", "<i4"), ("int64", "<i8"),
+	("uint8", "|u1"), ("uint16", "<u2"), ("uint32", "<u4"), ("uint64", "<u8"),
+	("bool", "|b1")
+];
+
+
+struct Dtype {
+	kind: u8, // f, i, u or b
+	size: usize,
+	big_endian: bool
+}
+
+impl Dtype {
+	fn parse (descr: &str) -> Option<Dtype> {
+		let bytes = descr.as_bytes();
+		if bytes.len() < 3 { return None; }
+		let big_endian = match bytes[0] {
+			b'<' | b'|' | b'=' => false, // native order is little endian on every platform numpy writes from in practice
+			b'>' => true,
+			_ => { return None; }
+		};
+		let size = descr.get(2..)?.parse::<usize>().ok()?;
+		let valid = match bytes[1] {
+			b'f' => size == 4 || size == 8,
+			b'i' | b'u' => [1, 2, 4, 8].contains(&size),
+			b'b' => size == 1,
+			_ => false
+		};
+		if valid { Some(Dtype { kind: bytes[1], size, big_endian }) }else { None }
+	}
+
+	fn read (&self, raw: &[u8]) -> Node {
+		let mut bytes = [0u8; 8];
+		bytes[..self.size].copy_from_slice(raw);
+		if self.big_endian { bytes[..self.size].reverse(); }
+		match (self.kind, self.size) {
+			(b'b', _) => Node::boolean(bytes[0] != 0),
+			(b'f', 4) => Node::number(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+			(b'f', _) => Node::number(f64::from_le_bytes(bytes) as f32),
+			(b'u', _) => Node::number(u64::from_le_bytes(bytes) as f32),
+			(_, size) => { // sign extend from the element's width
+				let shift = 64 - size as u32 * 8;
+				Node::number(((i64::from_le_bytes(bytes) << shift) >> shift) as f32)
+		}}
+	}
+
+	fn write<'a> (&self, out: &mut Vec<u8>, value: &Node, name: &'a str) -> Result<(), Err<'a>> {
+		let num = match *value.signature {
+			NodeClass::Number(num) => num,
+			NodeClass::Boolean(b) => if b { 1.0 }else { 0.0 },
+			_ => { return Err(Err::new(ErrorClass::TypeMismatch("Number", value.get_type()), value.clone())); }
+		};
+		let out_of_range = || Err::new(ErrorClass::Conversion(value.decode(), "Number", name), value.clone());
+		match (self.kind, self.size) {
+			(b'f', 4) => out.extend_from_slice(&num.to_le_bytes()),
+			(b'f', _) => out.extend_from_slice(&(num as f64).to_le_bytes()),
+			(b'b', _) => out.push((num != 0.0) as u8),
+			(kind, size) => {
+				let bits = size as u32 * 8;
+				let (min, max) = if kind == b'u' { (0.0, 2f64.powi(bits as i32)) }else { (-(2f64.powi(bits as i32 - 1)), 2f64.powi(bits as i32 - 1)) };
+				let num = num as f64;
+				if num.fract() != 0.0 || num < min || num >= max { return Err(out_of_range()); }
+				let raw = if kind == b'u' { (num as u64).to_le_bytes() }else { (num as i64).to_le_bytes() };
+				out.extend_from_slice(&raw[..size]);
+		}}
+		Ok(())
+	}
+}
+
+
+// the header is a python dict literal with string keys and string, boolean or tuple values
+struct Header {
+	descr: Option<String>,
+	fortran_order: bool,
+	shape: Option<Vec<usize>>
+}
+
+fn parse_header (text: &str) -> Option<Header> {
+	let mut header = Header { descr: None, fortran_order: false, shape: None };
+	let mut rest = text.trim().strip_prefix('{')?.trim_start();
+	let quoted = |text: &str| -> Option<(String, usize)> {
+		let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+		let end = text[1..].find(quote)? + 1;
+		Some((text[1..end].to_string(), end + 1))
+	};
+	while !rest.starts_with('}') {
+		let (key, used) = quoted(rest)?;
+		rest = rest[used..].trim_start().strip_prefix(':')?.trim_start();
+		match key.as_str() {
+			"descr" => {
+				let (descr, used) = quoted(rest)?; // structured dtypes are lists and aren't supported
+				header.descr = Some(descr);
+				rest = &rest[used..];
+			},
+			"fortran_order" => {
+				header.fortran_order = rest.starts_with("True");
+				rest = rest.strip_prefix("True").or_else(|| rest.strip_prefix("False"))?;
+			},
+			"shape" => {
+				let end = rest.find(')')?;
+				let dims = rest.strip_prefix('(')?[..end - 1].split(',').map(|dim| dim.trim()).filter(|dim| !dim.is_empty());
+				header.shape = Some(dims.map(|dim| dim.trim_end_matches('L').parse::<usize>().ok()).collect::<Option<Vec<usize>>>()?);
+				rest = &rest[end + 1..];
+			},
+			_ => { return None; }
+		}
+		rest = rest.trim_start();
+		rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+	}
+	Some(header)
+}
+
+
+// the number of elements in a shape, None if it overflows, has too many dimensions or nests into too many empty lists
+pub fn elements (shape: &[usize]) -> Option<usize> {
+	if shape.len() > MAX_DIMS { return None; }
+	let count = shape.iter().try_fold(1usize, |total, dim| total.checked_mul(*dim))?;
+	// a list for each index of every axis but the last, these are only unbacked by the data when an axis is 0
+	let lists = shape.iter().take(shape.len().saturating_sub(1)).scan(1usize, |total, dim| {
+		*total = total.saturating_mul(*dim);
+		Some(*total)
+	}).fold(1usize, |sum, lists| sum.saturating_add(lists));
+	if count == 0 && lists > MAX_EMPTY_LISTS { return None; }
+	Some(count)
+}
+
+
+// splits the flat elements into lists by the shape, a shape of () is a single value
+pub fn nest (shape: &[usize], items: &mut impl Iterator<Item = Node>) -> Node {
+	match shape.split_first() {
+		None => items.next().unwrap_or_else(Node::void),
+		Some((len, inner)) => Node::list((0..*len).map(|_| nest(inner, items)).collect())
+	}
+}
+
+
+// archive entries have to be exactly as long as their header says, plain files may have bytes after the data
+pub fn decode_npy<'a> (bytes: &[u8], path: &Node, exact: bool) -> Result<Node, Err<'a>> {
+	let malformed = |reason: &str| Err::file(format!("is not a valid .npy file: {}", reason), path);
+	if !bytes.starts_with(MAGIC) || bytes.len() < 10 { return Err(malformed("missing the NUMPY magic string")); }
+	let (header_len, start) = match bytes[6] {
+		1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+		2 | 3 if bytes.len() >= 12 => (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12),
+		_ => { return Err(malformed("unknown format version")); }
+	};
+	let text = bytes.get(start..start + header_len).and_then(|text| std::str::from_utf8(text).ok()).ok_or_else(|| malformed("truncated header"))?;
+	let header = parse_header(text).ok_or_else(|| malformed("unreadable header"))?;
+	let (descr, shape) = match (header.descr, header.shape) {
+		(Some(descr), Some(shape)) => (descr, shape),
+		_ => { return Err(malformed("header is missing descr or shape")); }
+	};
+	let dtype = Dtype::parse(&descr).ok_or_else(|| Err::file(format!("has the unsupported dtype '{}'", descr), path))?;
+
+	let count = elements(&shape).ok_or_else(|| malformed("shape is too large"))?;
+	let data = &bytes[start + header_len..];
+	if count.checked_mul(dtype.size).is_none_or(|len| len > data.len()) { return Err(malformed("data is shorter than its shape")); }
+	if exact && count * dtype.size != data.len() { return Err(malformed("entry is longer than its shape")); }
+	let flat: Vec<Node> = data.chunks_exact(dtype.size).take(count).map(|raw| dtype.read(raw)).collect();
+
+	if header.fortran_order && shape.len() > 1 {
+		// the first index varies fastest in the file, walk the indices in row major order to find each element
+		let mut strides = vec![1; shape.len()];
+		for axis in 1..shape.len() { strides[axis] = strides[axis - 1] * shape[axis - 1]; }
+		let mut index = vec![0; shape.len()];
+		let ordered = (0..count).map(|_| {
+			let offset: usize = index.iter().zip(strides.iter()).map(|(i, stride)| i * stride).sum();
+			for axis in (0..shape.len()).rev() {
+				index[axis] += 1;
+				if index[axis] < shape[axis] { break; }
+				index[axis] = 0;
+			}
+			flat[offset].clone()
+		}).collect::<Vec<Node>>();
+		return Ok(nest(&shape, &mut ordered.into_iter()));
+	}
+	Ok(nest(&shape, &mut flat.into_iter()))
+}
+
+
+// every list at one depth must have the same length, the shape is taken from the first element at each level
+fn flatten<'a> (value: &Node, shape: &[usize], out: &mut Vec<Node>) -> Result<(), Err<'a>> {
+	match shape.split_first() {
+		None if value.get_type() == "List" => Err(Err::new(ErrorClass::CustomError(String::from("Arrays must be rectangular, a list was found where a number was expected")), value.clone())),
+		None => {
+			out.push(value.clone());
+			Ok(())
+		},
+		Some((len, inner)) => {
+			let items = &value.validate_type("List")?.branches[0].branches;
+			if items.len() != *len { return Err(Err::new(ErrorClass::CustomError(format!("Arrays must be rectangular, expected a list of length {} but found {}", len, items.len())), value.clone())); }
+			for item in items.iter() { flatten(item, inner, out)?; }
+			Ok(())
+	}}
+}
+
+
+// the shape of a nested list and its elements in row major order, also used for fits images
+pub fn flatten_array<'a> (value: &Node) -> Result<(Vec<usize>, Vec<Node>), Err<'a>> {
+	let mut shape = Vec::new();
+	let mut level = value.clone();
+	while level.get_type() == "List" {
+		let items = level.branches[0].branches.clone();
+		shape.push(items.len());
+		if shape.len() > MAX_DIMS { return Err(Err::new(ErrorClass::CustomError(format!("Arrays can have at most {} dimensions", MAX_DIMS)), value.clone())); }
+		match items.first() {
+			Some(first) => { level = first.clone(); },
+			None => { break; }
+	}}
+	let mut flat = Vec::new();
+	flatten(value, &shape, &mut flat)?;
+	Ok((shape, flat))
+}
+
+
+pub fn encode_npy<'a> (value: &Node, dtype: Option<&Node>) -> Result<Vec<u8>, Err<'a>> {
+	let (shape, flat) = flatten_array(value)?;
+
+	let (name, descr) = match dtype {
+		Some(node) if node.get_type() != "Void" => {
+			let name = node.into_string()?;
+			match DTYPES.iter().find(|(known, _)| *known == name) {
+				Some(entry) => *entry,
+				None => { return Err(Err::new(ErrorClass::CustomError(format!("Unknown dtype '{}', expected one of {}", name, DTYPES.iter().map(|(known, _)| *known).collect::<Vec<&str>>().join(", "))), node.clone())); }
+		}},
+		// f32 widens to float64 exactly, booleans stay booleans
+		_ if !flat.is_empty() && flat.iter().all(|item| item.get_type() == "Boolean") => DTYPES[10],
+		_ => DTYPES[1]
+	};
+	let dtype = Dtype::parse(descr).unwrap();
+
+	let dims = match shape.len() {
+		1 => format!("{},", shape[0]),
+		_ => shape.iter().map(|dim| dim.to_string()).collect::<Vec<String>>().join(", ")
+	};
+	let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}), }}", descr, dims);
+	let long = header.len() + 11 > u16::MAX as usize; // version 2 has a 4 byte header length
+	let prefix = if long { 12 }else { 10 };
+	header.push_str(&" ".repeat((ALIGNMENT - (prefix + header.len() + 1) % ALIGNMENT) % ALIGNMENT));
+	header.push('\n');
+
+	let mut out = MAGIC.to_vec();
+	if long {
+		out.extend_from_slice(&[2, 0]);
+		out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+	}else {
+		out.extend_from_slice(&[1, 0]);
+		out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+	}
+	out.extend_from_slice(header.as_bytes());
+	for item in flat.iter() { dtype.write(&mut out, item, name)?; }
+	Ok(out)
+}
+
+
+pub fn load_npy<'a> (path: &Node) -> Result<Node, Err<'a>> {
+	match fs::read(path.into_string()?) {
+		Ok(bytes) => decode_npy(&bytes, path, false),
+		Err(err) => Err(Err::file(format!("could not be read: {}", err), path))
+	}
+}
+
+
+pub fn save_npy<'a> (path: &Node, value: &Node, dtype: Option<&Node>) -> Result<Node, Err<'a>> {
+	let bytes = encode_npy(value, dtype)?;
+	match fs::write(path.into_string()?, bytes) {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be written: {}", err), path))
+	}
+}
+
+
+// ---- zip archives
+
+
+fn crc32 (bytes: &[u8]) -> u32 {
+	let mut crc = 0xffffffffu32;
+	for byte in bytes.iter() {
+		crc ^= *byte as u32;
+		for _ in 0..8 { crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 }else { crc >> 1 }; }
+	}
+	!crc
+}
+
+
+fn u16_at (bytes: &[u8], at: usize) -> Option<u64> { Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u64) }
+
+fn u32_at (bytes: &[u8], at: usize) -> Option<u64> { Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as u64) }
+
+fn u64_at (bytes: &[u8], at: usize) -> Option<u64> { Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?)) }
+
+
+// (name, contents) of every entry, read through the central directory
+fn read_zip (bytes: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+	let end = (0..bytes.len().saturating_sub(21)).rev().find(|at| bytes[*at..].starts_with(b"PK\x05\x06"))?;
+	let count = u16_at(bytes, end + 10)?;
+	let mut at = u32_at(bytes, end + 16)? as usize;
+	let mut entries = Vec::new();
+
+	for _ in 0..count {
+		if !bytes.get(at..)?.starts_with(b"PK\x01\x02") { return None; }
+		let method = u16_at(bytes, at + 10)?;
+		let mut compressed = u32_at(bytes, at + 20)?;
+		let mut size = u32_at(bytes, at + 24)?;
+		let name_len = u16_at(bytes, at + 28)? as usize;
+		let extra_len = u16_at(bytes, at + 30)? as usize;
+		let comment_len = u16_at(bytes, at + 32)? as usize;
+		let mut offset = u32_at(bytes, at + 42)?;
+		let name = String::from_utf8_lossy(bytes.get(at + 46..at + 46 + name_len)?).to_string();
+
+		// zip64 entries keep their real sizes and offset in an extra field, in this order, for the values saturated at 0xffffffff
+		let mut extra = at + 46 + name_len;
+		let extra_end = extra + extra_len;
+		while extra + 4 <= extra_end {
+			let (id, len) = (u16_at(bytes, extra)?, u16_at(bytes, extra + 2)? as usize);
+			if id == 1 {
+				let mut field = extra + 4;
+				for value in [&mut size, &mut compressed, &mut offset] {
+					if *value == 0xffffffff {
+						*value = u64_at(bytes, field)?;
+						field += 8;
+				}}
+			}
+			extra += 4 + len;
+		}
+
+		let local = offset as usize;
+		if !bytes.get(local..)?.starts_with(b"PK\x03\x04") { return None; }
+		let data = local + 30 + u16_at(bytes, local + 26)? as usize + u16_at(bytes, local + 28)? as usize;
+		let raw = bytes.get(data..data + compressed as usize)?;
+		let contents = match method {
+			0 if size == compressed => raw.to_vec(),
+			8 => inflate(raw, size as usize)?,
+			_ => { return None; }
+		};
+		entries.push((name, contents));
+		at = extra_end + comment_len;
+	}
+	Some(entries)
+}
+
+
+// stored entries, dated 1980-01-01 so archives are reproducible
+fn write_zip (entries: &[(String, Vec<u8>)]) -> Option<Vec<u8>> {
+	let mut out = Vec::new();
+	let mut directory = Vec::new();
+	for (name, contents) in entries.iter() {
+		let offset = u32::try_from(out.len()).ok()?;
+		let size = u32::try_from(contents.len()).ok()?;
+		let mut fields = Vec::new();
+		fields.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]); // version, flags, method, time, date
+		fields.extend_from_slice(&crc32(contents).to_le_bytes());
+		fields.extend_from_slice(&size.to_le_bytes());
+		fields.extend_from_slice(&size.to_le_bytes());
+		fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+		fields.extend_from_slice(&[0, 0]); // extra length
+
+		out.extend_from_slice(b"PK\x03\x04");
+		out.extend_from_slice(&fields);
+		out.extend_from_slice(name.as_bytes());
+		out.extend_from_slice(contents);
+
+		directory.extend_from_slice(b"PK\x01\x02");
+		directory.extend_from_slice(&[20, 0]); // made by
+		directory.extend_from_slice(&fields);
+		directory.extend_from_slice(&[0; 8]); // comment length, disk, internal and external attributes
+		directory.extend_from_slice(&[0; 2]);
+		directory.extend_from_slice(&offset.to_le_bytes());
+		directory.extend_from_slice(name.as_bytes());
+	}
+	let start = u32::try_from(out.len()).ok()?;
+	out.extend_from_slice(&directory);
+	out.extend_from_slice(b"PK\x05\x06");
+	out.extend_from_slice(&[0; 4]); // disk numbers
+	let count = u16::try_from(entries.len()).ok()?;
+	out.extend_from_slice(&count.to_le_bytes());
+	out.extend_from_slice(&count.to_le_bytes());
+	out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+	out.extend_from_slice(&start.to_le_bytes());
+	out.extend_from_slice(&[0, 0]); // comment length
+	Some(out)
+}
+
+
+// ---- deflate decoding (RFC 1951), codes are canonical so a table of counts per length and the symbols in order is enough
+
+
+struct Bits<'d> {
+	data: &'d [u8],
+	pos: usize, // in bits
+}
+
+impl <'d> Bits<'d> {
+	fn take (&mut self, count: u32) -> Option<u32> {
+		let mut value = 0;
+		for i in 0..count {
+			let byte = *self.data.get(self.pos / 8)?;
+			value |= (((byte >> (self.pos % 8)) & 1) as u32) << i;
+			self.pos += 1;
+		}
+		Some(value)
+	}
+}
+
+
+struct Huffman {
+	counts: [u16; 16],
+	symbols: Vec<u16>
+}
+
+impl Huffman {
+	fn new (lengths: &[u8]) -> Huffman {
+		let mut counts = [0u16; 16];
+		for len in lengths.iter() { counts[*len as usize] += 1; }
+		counts[0] = 0;
+		let mut offsets = [0u16; 16];
+		for len in 1..16 { offsets[len] = offsets[len - 1] + counts[len - 1]; }
+		let mut symbols = vec![0; lengths.len()];
+		for (symbol, len) in lengths.iter().enumerate() {
+			if *len != 0 {
+				symbols[offsets[*len as usize] as usize] = symbol as u16;
+				offsets[*len as usize] += 1;
+		}}
+		Huffman { counts, symbols }
+	}
+
+	fn decode (&self, bits: &mut Bits) -> Option<u16> {
+		let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+		for len in 1..16 {
+			code |= bits.take(1)? as i32;
+			let count = self.counts[len] as i32;
+			if code - first < count { return self.symbols.get((index + code - first) as usize).copied(); }
+			index += count;
+			first = (first + count) << 1;
+			code <<= 1;
+		}
+		None
+	}
+}
+
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const MAX_DEFLATE_RATIO: usize = 1032; // the most output a byte of deflate data can produce
+const CODE_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+
+// the size comes from the archive and isn't trusted, decoding stops as soon as the output passes it
+fn inflate (data: &[u8], size: usize) -> Option<Vec<u8>> {
+	let mut out = Vec::with_capacity(size.min(data.len().saturating_mul(MAX_DEFLATE_RATIO)));
+	let mut bits = Bits { data, pos: 0 };
+	loop {
+		let last = bits.take(1)?;
+		match bits.take(2)? {
+			0 => { // stored, aligned to the next byte
+				let at = bits.pos.div_ceil(8);
+				let len = u16_at(data, at)? as usize;
+				if out.len() + len > size { return None; }
+				out.extend_from_slice(data.get(at + 4..at + 4 + len)?);
+				bits.pos = (at + 4 + len) * 8;
+			},
+			1 => {
+				let mut lengths = [8u8; 288];
+				lengths[144..256].fill(9);
+				lengths[256..280].fill(7);
+				inflate_block(&mut bits, &mut out, size, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+			},
+			2 => {
+				let literals = bits.take(5)? as usize + 257;
+				let distances = bits.take(5)? as usize + 1;
+				let code_count = bits.take(4)? as usize + 4;
+				let mut code_lengths = [0u8; 19];
+				for index in CODE_ORDER.iter().take(code_count) { code_lengths[*index] = bits.take(3)? as u8; }
+				let codes = Huffman::new(&code_lengths);
+
+				let mut lengths = Vec::with_capacity(literals + distances);
+				while lengths.len() < literals + distances {
+					let (value, repeat) = match codes.decode(&mut bits)? {
+						symbol @ 0..=15 => (symbol as u8, 1),
+						16 => (*lengths.last()?, 3 + bits.take(2)?),
+						17 => (0, 3 + bits.take(3)?),
+						_ => (0, 11 + bits.take(7)?)
+					};
+					for _ in 0..repeat { lengths.push(value); }
+				}
+				if lengths.len() != literals + distances { return None; }
+				inflate_block(&mut bits, &mut out, size, &Huffman::new(&lengths[..literals]), &Huffman::new(&lengths[literals..]))?;
+			},
+			_ => { return None; }
+		}
+		if last == 1 { return if out.len() == size { Some(out) }else { None }; }
+	}
+}
+
+
+fn inflate_block (bits: &mut Bits, out: &mut Vec<u8>, size: usize, literals: &Huffman, distances: &Huffman) -> Option<()> {
+	loop {
+		if out.len() > size { return None; }
+		match literals.decode(bits)? {
+			symbol @ 0..=255 => out.push(symbol as u8),
+			256 => { return Some(()); },
+			symbol => {
+				let index = symbol as usize - 257;
+				let len = *LENGTH_BASE.get(index)? as usize + bits.take(*LENGTH_EXTRA.get(index)? as u32)? as usize;
+				let code = distances.decode(bits)? as usize;
+				let dist = *DIST_BASE.get(code)? as usize + bits.take(*DIST_EXTRA.get(code)? as u32)? as usize;
+				if dist > out.len() || out.len() + len > size { return None; }
+				let start = out.len() - dist;
+				for i in 0..len { out.push(out[start + i]); } // the copy may overlap what it writes
+		}}
+	}
+}
+
+
+// an Npz instance with one field per array, named like np.load names them
+pub fn load_npz<'a> (path: &Node) -> Result<Node, Err<'a>> {
+	let bytes = match fs::read(path.into_string()?) {
+		Ok(bytes) => bytes,
+		Err(err) => { return Err(Err::file(format!("could not be read: {}", err), path)); }
+	};
+	let entries = read_zip(&bytes).ok_or_else(|| Err::file(String::from("is not a valid .npz archive"), path))?;
+	let mut fields = Vec::new();
+	for (name, contents) in entries.iter() {
+		let stem = match name.strip_suffix(".npy") {
+			Some(stem) => stem,
+			None => { continue; }
+		};
+		fields.push((stem.to_string(), decode_npy(contents, path, true)?));
+	}
+	Ok(Node::instance("Npz", fields))
+}
+
+
+// arrays is an object instance whose fields are the array names, or a list saved as arr_0, arr_1... like np.savez
+pub fn save_npz<'a> (path: &Node, arrays: &Node, dtype: Option<&Node>) -> Result<Node, Err<'a>> {
+	let named: Vec<(String, Node)> = match arrays.get_type() {
+		"List" => arrays.branches[0].branches.iter().enumerate().map(|(index, array)| (format!("arr_{}", index), array.clone())).collect(),
+		"ObjectInstance" if arrays.branches.len() == 2 => arrays.branches[0].branches.iter().zip(arrays.branches[1].branches.iter())
+			.filter(|(_, array)| array.id().0 != "<extern_link>")
+			.map(|(name, array)| (name.id().0.to_string(), array.clone())).collect(),
+		_ => { return Err(Err::new(ErrorClass::TypeMismatch("ObjectInstance", arrays.get_type()), arrays.clone())); }
+	};
+	let mut entries = Vec::new();
+	for (name, array) in named.iter() { entries.push((format!("{}.npy", name), encode_npy(array, dtype)?)); }
+	let bytes = write_zip(&entries).ok_or_else(|| Err::file(String::from("is too large for a zip archive without zip64"), path))?;
+	match fs::write(path.into_string()?, bytes) {
+		Ok(()) => Ok(Node::void()),
+		Err(err) => Err(Err::file(format!("could not be written: {}", err), path))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::{ env, process };
+	use super::*;
+	
+	fn npy (header: &str, data: &[u8]) -> Vec<u8> {
+		let mut out = MAGIC.to_vec();
+		out.extend_from_slice(&[1, 0]);
+		out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+		out.extend_from_slice(header.as_bytes());
+		out.extend_from_slice(data);
+		out
+	}
+	
+	fn matrix (rows: &[&[f32]]) -> Node { Node::list(rows.iter().map(|row| Node::list(row.iter().map(|num| Node::number(*num)).collect())).collect()) }
+	
+	fn decoded (bytes: &[u8]) -> Result<Node, String> { decode_npy(bytes, &Node::string(String::from("<npy>")), false).map_err(|err| err.name().to_string()) }
+	
+	// one entry archive, sizes past 32 bits go in a zip64 extra field
+	fn zip (name: &str, method: u16, raw: &[u8], size: u64) -> Vec<u8> {
+		let (listed, extra) = match u32::try_from(size) {
+			Ok(size) if size != 0xffffffff => (size, Vec::new()),
+			_ => (0xffffffff, [&[1, 0, 8, 0][..], &size.to_le_bytes()].concat())
+		};
+		let mut fields = vec![20, 0, 0, 0];
+		fields.extend_from_slice(&method.to_le_bytes());
+		fields.extend_from_slice(&[0; 8]); // time, date and crc
+		fields.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+		fields.extend_from_slice(&listed.to_le_bytes());
+		fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+		
+		let mut out = b"PK\x03\x04".to_vec();
+		out.extend_from_slice(&fields);
+		out.extend_from_slice(&[0, 0]);
+		out.extend_from_slice(name.as_bytes());
+		out.extend_from_slice(raw);
+		let start = out.len();
+		out.extend_from_slice(b"PK\x01\x02\x14\x00");
+		out.extend_from_slice(&fields);
+		out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+		out.extend_from_slice(&[0; 14]); // comment length, disk, attributes and the local header's offset
+		out.extend_from_slice(name.as_bytes());
+		out.extend_from_slice(&extra);
+		let directory = out.len() - start;
+		out.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00\x01\x00\x01\x00");
+		out.extend_from_slice(&(directory as u32).to_le_bytes());
+		out.extend_from_slice(&(start as u32).to_le_bytes());
+		out.extend_from_slice(&[0, 0]);
+		out
+	}
+	
+	// a fixed huffman block with one literal and then copies of length 258 at distance 1
+	fn deflated (literal: u8, copies: usize) -> Vec<u8> {
+		let mut bits: Vec<u8> = vec![1, 1, 0]; // last block, type 1
+		let mut code = |value: u32, len: u32| for i in (0..len).rev() { bits.push(((value >> i) & 1) as u8); }; // codes are sent high bit first
+		code(0x30 + literal as u32, 8);
+		for _ in 0..copies {
+			code(0xc5, 8); // symbol 285
+			code(0, 5);
+		}
+		code(0, 7); // end of block
+		bits.chunks(8).map(|byte| byte.iter().enumerate().fold(0, |acc, (i, bit)| acc | (bit << i))).collect()
+	}
+	
+	#[test]
+	fn round_trip () {
+		let value = matrix(&[&[1.0, -2.0, 3.0], &[4.0, 5.0, 126.0]]);
+		for (dtype, _) in DTYPES.iter().filter(|(name, _)| !name.starts_with('u') && *name != "bool") {
+			let bytes = encode_npy(&value, Some(&Node::string(dtype.to_string()))).unwrap();
+			assert!(decoded(&bytes).unwrap() == value, "{} changed the values", dtype);
+			let data = bytes.len() - 6 * Dtype::parse(DTYPES.iter().find(|(name, _)| name == dtype).unwrap().1).unwrap().size;
+			assert_eq!(data % ALIGNMENT, 0);
+		}
+		let flags = Node::list(vec![Node::boolean(true), Node::boolean(false)]);
+		assert!(decoded(&encode_npy(&flags, None).unwrap()).unwrap() == flags);
+		assert!(decoded(&encode_npy(&Node::list(Vec::new()), None).unwrap()).unwrap() == Node::list(Vec::new()));
+		assert!(encode_npy(&Node::list(vec![Node::number(256.0)]), Some(&Node::string(String::from("uint8")))).is_err());
+		assert!(encode_npy(&matrix(&[&[1.0], &[2.0, 3.0]]), None).is_err());
+	}
+	
+	#[test]
+	fn file_order () {
+		let data: Vec<u8> = [1i16, 4, 2, 5, 3, 6].iter().flat_map(|num| num.to_be_bytes()).collect();
+		let value = decoded(&npy("{'descr': '>i2', 'fortran_order': True, 'shape': (2, 3), }\n", &data)).unwrap();
+		assert!(value == matrix(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]));
+		let value = decoded(&npy("{\"descr\": \"<u8\", \"shape\": (1L,)}", &[0xff; 8])).unwrap();
+		assert!(value == Node::list(vec![Node::number(u64::MAX as f32)]));
+	}
+	
+	// headers that used to panic or allocate without bound
+	#[test]
+	fn malformed_headers () {
+		let header = |shape: &str| format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}", shape);
+		assert!(decoded(&npy("{'descr': 'é4', 'fortran_order': False, 'shape': (1,), }", &[0; 4])).is_err());
+		assert!(decoded(&npy(&header("(100000000000, 0)"), &[])).is_err());
+		assert!(decoded(&npy(&header("(4611686018427387904, 4)"), &[0; 16])).is_err());
+		assert!(decoded(&npy(&header(&format!("({})", "1, ".repeat(MAX_DIMS + 1))), &[0; 4])).is_err());
+		assert!(decoded(&npy(&header("(4,)"), &[0; 8])).is_err());
+		assert!(decoded(&npy(&header("(1,"), &[0; 4])).is_err());
+		assert!(decoded(&MAGIC[..5]).is_err());
+		let mut future = npy(&header("(1,)"), &[0; 4]);
+		future[6] = 9;
+		assert!(decoded(&future).is_err());
+		future[6] = 1;
+		future[8] = 0xff;
+		assert!(decoded(&future).is_err());
+		
+		let deep = (0..MAX_DIMS + 1).fold(Node::number(0.0), |value, _| Node::list(vec![value]));
+		assert!(encode_npy(&deep, None).is_err());
+	}
+	
+	#[test]
+	fn archives () {
+		let path = Node::string(env::temp_dir().join(format!("kylu-npz-{}.npz", process::id())).to_string_lossy().to_string());
+		let arrays = Node::instance("Arrays", vec![(String::from("x"), matrix(&[&[1.0, 2.0]])), (String::from("y"), Node::list(vec![Node::boolean(true)]))]);
+		save_npz(&path, &arrays, None).unwrap();
+		let loaded = load_npz(&path).unwrap();
+		assert_eq!(loaded.id().0, "Npz");
+		assert!(loaded.branches[1] == arrays.branches[1]);
+		fs::remove_file(path.into_string().unwrap()).unwrap();
+		
+		let entry = encode_npy(&matrix(&[&[7.0]]), None).unwrap();
+		assert_eq!(read_zip(&zip("a.npy", 0, &entry, entry.len() as u64)), Some(vec![(String::from("a.npy"), entry.clone())]));
+		assert_eq!(read_zip(&zip("a.npy", 0, &entry, entry.len() as u64 + 1)), None);
+		// an entry longer than its array is rejected inside an archive, a plain file may have bytes after the data
+		let longer = [&entry[..], &[0; 8]].concat();
+		assert!(decode_npy(&longer, &Node::void(), true).is_err());
+		assert!(decode_npy(&longer, &Node::void(), false).is_ok());
+	}
+	
+	#[test]
+	fn inflate_limits () {
+		let data = deflated(b'a', 4);
+		assert_eq!(inflate(&data, 1 + 4 * 258), Some(vec![b'a'; 1 + 4 * 258]));
+		assert_eq!(inflate(&data, 4 * 258), None); // longer than declared
+		assert_eq!(inflate(&data, 1 + 4 * 258 + 1), None); // shorter than declared
+		
+		// a small entry that claims a huge size, or expands far past the one it claims
+		let bomb = deflated(0, 10000);
+		assert!(read_zip(&zip("big.npy", 8, &deflated(b'a', 4), 1 << 62)).is_none());
+		assert!(read_zip(&zip("bomb.npy", 8, &bomb, 1000)).is_none());
+		assert!(read_zip(&zip("stored.npy", 8, &[1, 4, 0, 0xfb, 0xff, 1, 2, 3, 4], 2)).is_none());
+		assert_eq!(read_zip(&zip("stored.npy", 8, &[1, 4, 0, 0xfb, 0xff, 1, 2, 3, 4], 4)), Some(vec![(String::from("stored.npy"), vec![1, 2, 3, 4])]));
+	}
+}
@@ -0,0 +1,112 @@
+use std::{ env, path::PathBuf };
+use rustyline::completion::{ Completer, Pair };
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ ValidationContext, ValidationResult, Validator };
+use rustyline::{ Context, Helper };
+use crate::evaluator::NameSpace;
+use crate::utils::builtin_funcs::LIBRARY;
+
+
+// command, arguments, description, shown by /help
+pub const COMMANDS: [(&str, &str, &str); 14] = [
+	("/help", "", "list terminal commands and builtin functions"),
+	("/exit", "", "stop the terminal, also /x"),
+	("/edit", "", "enter several lines of code, finish with /done"),
+	("/bindings", "", "show local bindings, also /bind"),
+	("/extensions", "", "show the exported bindings of loaded modules, also /ext"),
+	("/load", "<file>", "run a file and import its bindings"),
+	("/reset", "", "clear every binding and the session"),
+	("/del", "<name>", "remove a local binding"),
+	("/ast", "<expr>", "show the parsed tree for an expression"),
+	("/type", "<expr>", "show the type of an expression's value"),
+	("/time", "<expr>", "evaluate an expression and show how long it took"),
+	("/save", "<file>", "write this session's code to a file that can be run again"),
+	("/snapshot", "save|load <file>", "write every binding to a workspace file, or restore them from one"),
+	("/done", "", "finish an /edit block")
+];
+const HISTORY_FILE: &str = ".kylu_history";
+
+
+// history is kept in the home directory, or the working directory if there isn't one
+pub fn history_path () -> PathBuf {
+	match env::var_os("HOME") {
+		Some(home) => PathBuf::from(home).join(HISTORY_FILE),
+		None => PathBuf::from(HISTORY_FILE)
+	}
+}
+
+
+// net count of open brackets outside of string literals, negative if there are stray closing brackets
+pub fn open_brackets (source: &str) -> isize {
+	let mut depth = 0;
+	let mut quote: Option<char> = None;
+	for c in source.chars() {
+		match (quote, c) {
+			(Some(q), c) if q == c => { quote = None; },
+			(Some(_), _) => (),
+			(None, '"') | (None, '\'') => { quote = Some(c); },
+			(None, '{') | (None, '(') | (None, '[') => { depth += 1; },
+			(None, '}') | (None, ')') | (None, ']') => { depth -= 1; },
+			_ => ()
+	}}
+	depth
+}
+
+
+// editor hooks for the terminal, namespaces are shared so new bindings show up in completions immediately
+pub struct KyluHelper {
+	pub bindings: NameSpace,
+	pub imports: NameSpace
+}
+
+impl KyluHelper {
+	fn candidates (&self, line: &str) -> Vec<String> {
+		if line.starts_with('/') { return COMMANDS.iter().map(|comm| comm.0.to_string()).collect(); }
+		let mut names = self.bindings.names();
+		names.extend(self.imports.names());
+		names.extend(LIBRARY.iter().map(|name| name.to_string()));
+		names
+	}
+}
+
+impl Completer for KyluHelper {
+	type Candidate = Pair;
+
+	fn complete (&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+		// complete the symbol (or slash command) that ends at the cursor
+		let start = line[..pos].rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '/')).map(|idx| idx + 1).unwrap_or(0);
+		let word = &line[start..pos];
+		if word.is_empty() { return Ok((pos, Vec::new())); }
+
+		let mut matches: Vec<String> = self.candidates(word).into_iter().filter(|name| name.starts_with(word)).collect();
+		matches.sort();
+		matches.dedup();
+		Ok((start, matches.into_iter().map(|name| Pair { display: name.clone(), replacement: name }).collect()))
+	}
+}
+
+impl Validator for KyluHelper {
+	// keep reading lines while brackets are left open, slash commands are always a single line
+	fn validate (&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+		let input = ctx.input();
+		if !input.starts_with('/') && open_brackets(input) > 0 { return Ok(ValidationResult::Incomplete); }
+		Ok(ValidationResult::Valid(None))
+	}
+}
+
+impl Hinter for KyluHelper { type Hint = String; }
+
+impl Highlighter for KyluHelper {}
+
+impl Helper for KyluHelper {}
+
+
+pub fn help () -> String {
+	let mut text = String::new();
+	for (command, args, description) in COMMANDS.iter() {
+		text.push_str(&format!("{:<28} {}\n", format!("{} {}", command, args), description));
+	}
+	text.push_str(&format!("\nbuiltin functions: {}", LIBRARY.join(", ")));
+	text
+}
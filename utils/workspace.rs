@@ -0,0 +1,260 @@
+use std::fs;
+use std::sync::Arc;
+use crate::evaluator::{ Env, NameSpace };
+use crate::compiler::compile_body;
+use crate::utils::node::{ NodeClass, Node, intern };
+use crate::utils::error::{ ErrorClass, Err };
+
+
+/*
+workspace files hold the global and imported namespaces as node trees, one node is written as
+	tag [payload] span_start span_end branch_count branches...
+separated by single spaces. text payloads are length prefixed (5:hello) so they can hold anything,
+numbers are stored as their bit pattern so they come back exactly. compiled code isn't saved,
+objects are compiled again from their ast while the workspace is loaded.
+the script's args binding isn't saved so loading a workspace doesn't replace it
+*/
+const HEADER: &str = "kylu-workspace 1";
+
+
+fn text (out: &mut String, val: &str) { out.push_str(&format!("{}:{} ", val.len(), val)); }
+
+
+// a node's tag, payload and position, the branch count is added by encode
+fn encode_node (out: &mut String, node: &Node) {
+	match *node.signature {
+		NodeClass::String(ref val) => { out.push_str("s "); text(out, val); },
+		NodeClass::Number(val) => { out.push_str(&format!("n {:08x} ", val.to_bits())); },
+		NodeClass::Boolean(val) => { out.push_str(if val { "t " }else { "f " }); },
+		NodeClass::Symbol(ref val) => { out.push_str("y "); text(out, val); },
+		NodeClass::Local(ref val, slot) => { out.push_str(&format!("L {} ", slot)); text(out, val); },
+		NodeClass::Oper(ref val, prec) => { out.push_str(&format!("O {} ", prec)); text(out, val); },
+		NodeClass::Misc(ref val) => { out.push_str("M "); text(out, val); },
+		NodeClass::Void => { out.push_str("v "); },
+		NodeClass::OperExpr(ref val) => { out.push_str("E "); text(out, val); },
+		NodeClass::ObjectInst(ref val) => { out.push_str("I "); text(out, val); },
+		NodeClass::Loop(ref val) => { out.push_str("W "); text(out, val); },
+		NodeClass::Field => { out.push_str("F "); },
+		NodeClass::List => { out.push_str("A "); },
+		NodeClass::Paren => { out.push_str("P "); },
+		NodeClass::Object => { out.push_str("B "); },
+		NodeClass::Combinator => { out.push_str("C "); },
+		NodeClass::Call => { out.push_str("K "); },
+		NodeClass::If => { out.push_str("Q "); },
+		NodeClass::Try => { out.push_str("T "); },
+		NodeClass::Import => { out.push_str("U "); },
+		NodeClass::Export => { out.push_str("X "); },
+		NodeClass::Code(_) => { out.push_str("v "); } // only reached if a code node is bound directly
+	}
+}
+
+
+// nodes are written parent first, with a work list since bound lists can be nested deeper than the stack allows
+fn encode (node: &Node) -> String {
+	let mut out = String::new();
+	let mut pending = vec![node];
+	while let Some(node) = pending.pop() {
+		encode_node(&mut out, node);
+		let branches: Vec<&Node> = node.branches.iter().filter(|branch| !matches!(*branch.signature, NodeClass::Code(_))).collect();
+		out.push_str(&format!("{} {} {} ", node.span[0], node.span[1], branches.len()));
+		pending.extend(branches.into_iter().rev());
+	}
+	out
+}
+
+
+struct Reader<'s> {
+	source: &'s str,
+	index: usize
+}
+
+impl <'s> Reader<'s> {
+	fn word (&mut self) -> Option<&'s str> {
+		let rest = self.source.get(self.index..)?;
+		let end = rest.find(' ')?;
+		self.index += end + 1;
+		Some(&rest[..end])
+	}
+
+	fn number<T: std::str::FromStr> (&mut self) -> Option<T> { self.word()?.parse::<T>().ok() }
+
+	fn text (&mut self) -> Option<String> {
+		let rest = self.source.get(self.index..)?;
+		let colon = rest.find(':')?;
+		let len = rest[..colon].parse::<usize>().ok()?;
+		let val = rest.get(colon + 1..colon + 1 + len)?.to_string();
+		if rest.get(colon + 1 + len..colon + 2 + len) != Some(" ") { return None; }
+		self.index += colon + 2 + len;
+		Some(val)
+	}
+
+	// everything written for a node before its branches
+	fn header (&mut self) -> Option<(NodeClass, [usize; 2], usize)> {
+		let signature = match self.word()? {
+			"s" => NodeClass::String(self.text()?),
+			"n" => NodeClass::Number(f32::from_bits(u32::from_str_radix(self.word()?, 16).ok()?)),
+			"t" => NodeClass::Boolean(true),
+			"f" => NodeClass::Boolean(false),
+			"y" => NodeClass::Symbol(self.text()?),
+			"L" => {
+				let slot = self.number()?;
+				NodeClass::Local(self.text()?, slot)
+			},
+			"O" => {
+				let prec = self.number()?;
+				NodeClass::Oper(self.text()?, prec)
+			},
+			"M" => NodeClass::Misc(self.text()?),
+			"v" => NodeClass::Void,
+			"E" => NodeClass::OperExpr(self.text()?),
+			"I" => NodeClass::ObjectInst(self.text()?),
+			"W" => NodeClass::Loop(self.text()?),
+			"F" => NodeClass::Field,
+			"A" => NodeClass::List,
+			"P" => NodeClass::Paren,
+			"B" => NodeClass::Object,
+			"C" => NodeClass::Combinator,
+			"K" => NodeClass::Call,
+			"Q" => NodeClass::If,
+			"T" => NodeClass::Try,
+			"U" => NodeClass::Import,
+			"X" => NodeClass::Export,
+			_ => { return None; }
+		};
+		let span = [self.number()?, self.number()?];
+		let len: usize = self.number()?;
+		Some((signature, span, len))
+	}
+
+	// nodes whose branches are still being read are kept on a stack, so nesting depth isn't limited by recursion
+	fn node (&mut self) -> Option<Node> {
+		let mut open: Vec<(NodeClass, [usize; 2], usize, Vec<Node>)> = Vec::new();
+		loop {
+			let (signature, span, len) = self.header()?;
+			if len > 0 {
+				open.push((signature, span, len, Vec::new()));
+				continue;
+			}
+			let mut done = finish(signature, span, Vec::new());
+			loop {
+				let parent = match open.last_mut() {
+					Some(parent) => parent,
+					None => { return Some(done); }
+				};
+				parent.3.push(done);
+				if parent.3.len() < parent.2 { break; }
+				let (signature, span, _, branches) = open.pop()?;
+				done = finish(signature, span, branches);
+			}
+		}
+	}
+}
+
+
+fn finish (signature: NodeClass, span: [usize; 2], mut branches: Vec<Node>) -> Node {
+	if signature == NodeClass::Object && branches.len() == 2 {
+		// a body that doesn't compile is left to code_for, which reports the error when it's called
+		if let Ok(code) = compile_body(&branches[1]) { branches.push(Node::new(NodeClass::Code(Arc::new(code)), [0, 0], Vec::new())); }
+	}
+	Node::new(signature, span, branches)
+}
+
+
+// the namespace nodes that follow the header, text payloads may contain newlines so the body isn't split into lines
+fn decode (source: &str) -> Option<(Node, Node)> {
+	let mut reader = Reader { source: source.strip_prefix(HEADER)?.strip_prefix("\n")?, index: 0 };
+	let globals = reader.node()?;
+	let imports = reader.node()?;
+	if reader.source[reader.index..].trim() != "" { return None; }
+	Some((globals, imports))
+}
+
+
+// copy every binding of a namespace node (as made by NameSpace::as_node) into the namespace
+fn restore (target: &NameSpace, bindings: &Node) -> Option<()> {
+	let (keys, values) = (bindings.branches.first()?, bindings.branches.get(1)?);
+	if keys.branches.len() != values.branches.len() { return None; }
+	for (key, value) in keys.branches.iter().zip(values.branches.iter()) {
+		target.assign(key.key_id(), key, value.clone());
+	}
+	Some(())
+}
+
+
+// writes the global namespace and imported modules
+pub fn save_workspace<'a> (env: &Env, path: &str) -> Result<(), Err<'a>> {
+	let mut bindings = env.data[0].as_node(Node::symbol("<workspace>".to_string()));
+	while let Some(slot) = bindings.branches[0].branches.iter().position(|key| key.key_id() == intern("args")) {
+		let fields = bindings.branches_mut();
+		fields[0].branches_mut().remove(slot);
+		fields[1].branches_mut().remove(slot);
+	}
+	let globals = encode(&bindings);
+	let imports = encode(&env.import.as_node(Node::symbol("<workspace>".to_string())));
+	match fs::write(path, format!("{}\n{}{}\n", HEADER, globals, imports)) {
+		Ok(()) => Ok(()),
+		Err(_) => Err(Err::new(ErrorClass::File("could not be written".to_string(), path.to_string()), Node::string(path.to_string())))
+	}
+}
+
+
+// bindings from the file replace ones with the same name, everything else is kept
+pub fn load_workspace<'a> (env: &Env, path: &str) -> Result<(), Err<'a>> {
+	let source = match fs::read_to_string(path) {
+		Ok(source) => source,
+		Err(_) => { return Err(Err::new(ErrorClass::File("could not be read".to_string(), path.to_string()), Node::string(path.to_string()))); }
+	};
+	let invalid = || Err::new(ErrorClass::File("is not a valid workspace".to_string(), path.to_string()), Node::string(path.to_string()));
+
+	let (globals, imports) = decode(&source).ok_or_else(invalid)?;
+	restore(&env.data[0], &globals).ok_or_else(invalid)?;
+	restore(&env.import, &imports).ok_or_else(invalid)?;
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::{ env, process };
+	use crate::evaluator::eval_file;
+	use super::*;
+	
+	fn workspace_path (name: &str) -> String { env::temp_dir().join(format!("kylu-{}-{}.kyw", name, process::id())).to_string_lossy().to_string() }
+	
+	#[test]
+	fn round_trip () {
+		let path = workspace_path("round-trip");
+		let saved = Env::create();
+		eval_file(saved.clone(), "<workspace>", String::from("f <- obj(a, b) { if (a > b) { out(a) } { b } }\nl <- [1, [True, \"x\"], []]\nv <- Void\nargs <- [1]\n"), false).unwrap();
+		saved.data[0].set(Node::symbol("s".to_string()), Node::string("two\nlines 5:x".to_string())).unwrap();
+		saved.data[0].set(Node::symbol("n".to_string()), Node::number(f32::MIN_POSITIVE)).unwrap();
+		save_workspace(&saved, &path).unwrap();
+		
+		let loaded = Env::create();
+		load_workspace(&loaded, &path).unwrap();
+		for name in ["s", "n", "l", "v"] {
+			assert!(loaded.data[0].get(Node::symbol(name.to_string())).unwrap() == saved.data[0].get(Node::symbol(name.to_string())).unwrap(), "{} changed", name);
+		}
+		assert!(loaded.data[0].get(Node::symbol("args".to_string())).is_err()); // the script's arguments aren't saved
+		// objects are compiled again while loading
+		assert_eq!(eval_file(loaded, "<workspace>", String::from("f(3, 2) + f(1, 2)\n"), false).map(|value| value.decode()), Some(String::from("5")));
+		fs::remove_file(path).unwrap();
+	}
+	
+	// a cut off or edited file is reported as invalid
+	#[test]
+	fn truncated_file () {
+		let path = workspace_path("truncated");
+		let saved = Env::create();
+		eval_file(saved.clone(), "<workspace>", String::from("f <- obj(a) { a * 2 }\nl <- [1, [2, \"three\"]]\n"), false).unwrap();
+		save_workspace(&saved, &path).unwrap();
+		let contents = fs::read_to_string(&path).unwrap();
+		for end in (0..contents.trim_end().len()).filter(|end| contents.is_char_boundary(*end)) {
+			fs::write(&path, &contents[..end]).unwrap();
+			assert!(load_workspace(&Env::create(), &path).is_err(), "a workspace cut at {} was loaded", end);
+		}
+		fs::write(&path, format!("{}\n2147483647 ", HEADER)).unwrap();
+		assert!(load_workspace(&Env::create(), &path).is_err());
+		fs::remove_file(path).unwrap();
+	}
+}
@@ -0,0 +1,532 @@
+use std::sync::Arc;
+use crate::compiler::{ Op, Chunk, Site, code_for };
+use crate::evaluator::{ Env, NameSpace, import_module };
+use crate::utils::node::{ NodeClass, Node };
+use crate::utils::error::{ ErrorClass, Err };
+use crate::utils::builtin_funcs::{ LIBRARY, search_library };
+
+
+// handlers that are active while a chunk runs, the innermost one is last
+enum Block<'a> {
+	Loop {
+		depth: usize,
+		height: usize, // operand stack length when the loop started
+		trace: usize,
+		head: usize,
+		exit: usize,
+		armed: bool, // only errors raised by the loop body are caught
+		items: Option<(Arc<Vec<Node>>, usize)>, // iterator and position for iter loops
+		comp: Vec<Node>
+	},
+	Expect { depth: usize, height: usize, trace: usize, handler: usize, kind: usize },
+	Try { depth: usize, height: usize, trace: usize, table: usize, kinds: usize },
+	Finally {
+		depth: usize,
+		height: usize,
+		trace: usize,
+		cleanup: usize,
+		running: bool, // errors raised by the cleanup itself replace the pending one
+		pending: Option<(Err<'a>, Vec<Site>)> // interrupting error and its frames, re-raised when the cleanup ends
+	}
+}
+
+
+fn site_node (site: &Site) -> Node { Node::new(site.0.clone(), site.1, Vec::new()) }
+
+
+fn unwind (env: &mut Env, depth: usize) {
+	env.data.truncate(depth + 1);
+	env.depth = depth;
+}
+
+
+pub fn run<'a> (env: &mut Env, chunk: &Chunk) -> Result<Node, Err<'a>> {
+	let base = env.depth;
+	let mut stack: Vec<Node> = Vec::new();
+	let mut blocks: Vec<Block> = Vec::new();
+	let mut pc = 0;
+
+	while pc < chunk.code.len() {
+		let op = chunk.code[pc];
+		pc += 1;
+		if let Err(e) = step(env, chunk, op, &mut stack, &mut blocks, &mut pc) {
+			if op != Op::EndFinally { env.trace.push(chunk.sites[pc - 1].clone()); } // a re-raised error already has its frames
+			match recover(env, chunk, &mut stack, &mut blocks, e) {
+				Ok(target) => { pc = target; },
+				Err(e) => {
+					unwind(env, base);
+					return Err(e);
+			}}
+	}}
+	Ok(stack.pop().unwrap_or_else(Node::void))
+}
+
+
+// run a chunk inside the given namespace
+pub fn run_scoped<'a> (env: &mut Env, scope: NameSpace, chunk: &Chunk) -> Result<Node, Err<'a>> {
+	env.depth += 1;
+	env.data.push(scope);
+	let result = run(env, chunk);
+	env.depth -= 1;
+	env.data.pop();
+	result
+}
+
+
+// find the innermost block that accepts the error and return the address to resume at
+fn recover<'a> (env: &mut Env, chunk: &Chunk, stack: &mut Vec<Node>, blocks: &mut Vec<Block<'a>>, err: Err<'a>) -> Result<usize, Err<'a>> {
+	if let ErrorClass::LimitExceeded(_) = *err.class {
+		if env.budget.spent() { return Err(err); } // handlers already had their allowance
+	}
+	while let Some(block) = blocks.last_mut() {
+		match block {
+			Block::Loop { depth, height, trace, head, exit, armed, .. } => {
+				if *armed {
+					let target = match *err.class {
+						ErrorClass::Signal("StopIteration") => Some(*exit),
+						ErrorClass::Signal("ResetIteration") => Some(*head),
+						_ => None
+					};
+					if let Some(target) = target {
+						*armed = false;
+						unwind(env, *depth);
+						stack.truncate(*height);
+						env.trace.truncate(*trace);
+						return Ok(target);
+			}}},
+			Block::Expect { depth, height, trace, handler, kind } => {
+				// the user wants out, a handler shouldn't keep the code running but outer cleanups still run
+				if ErrorClass::Signal("KeyboardInterrupt") != *err.class && chunk.constants[*kind].id() == (err.name(), "String") {
+					// frames are pushed while unwinding, so the first one since the block was set up is the innermost
+					let span = env.trace.get(*trace).map(|frame| frame.1).unwrap_or(err.cause.span);
+					let caught = err.to_node(&env.file, &env.source, span)?;
+					if let ErrorClass::LimitExceeded(_) = *err.class { env.budget.grace(); }
+					let target = *handler;
+					unwind(env, *depth);
+					stack.truncate(*height);
+					env.trace.truncate(*trace);
+					stack.push(caught);
+					blocks.pop();
+					return Ok(target);
+			}},
+			Block::Try { depth, height, trace, table, kinds } => {
+				match *err.class { // control flow is never caught by a handler
+					ErrorClass::Signal(_) => (),
+					_ => {
+						// the first clause that lists the error's kind, or doesn't list any
+						let clause = chunk.constants[*kinds].branches.iter().position(|accepted| {
+							accepted.branches.is_empty() || accepted.branches.iter().any(|kind| kind.id() == (err.name(), "String"))
+						});
+						if let Some(clause) = clause {
+							let span = env.trace.get(*trace).map(|frame| frame.1).unwrap_or(err.cause.span);
+							let caught = err.to_node(&env.file, &env.source, span)?;
+							if let ErrorClass::LimitExceeded(_) = *err.class { env.budget.grace(); }
+							let target = *table + clause;
+							unwind(env, *depth);
+							stack.truncate(*height);
+							env.trace.truncate(*trace);
+							stack.push(caught);
+							blocks.pop();
+							return Ok(target);
+				}}}
+			},
+			Block::Finally { depth, height, trace, cleanup, running, pending } => {
+				if !*running {
+					// the cleanup runs for every error and signal, the error is raised again after it
+					if let ErrorClass::LimitExceeded(_) = *err.class { env.budget.grace(); }
+					unwind(env, *depth);
+					stack.truncate(*height);
+					stack.push(Node::void()); // stands in for the try's value
+					*pending = Some((err, env.trace.split_off(*trace)));
+					return Ok(*cleanup);
+			}}
+		}
+		blocks.pop();
+	}
+	Err(err)
+}
+
+
+// armed flag, iterator and comprehension of the innermost loop
+type LoopState<'b> = (&'b mut bool, &'b mut Option<(Arc<Vec<Node>>, usize)>, &'b mut Vec<Node>);
+
+fn current_loop<'a, 'b> (blocks: &'b mut [Block<'a>]) -> Result<LoopState<'b>, Err<'a>> {
+	match blocks.last_mut() {
+		Some(Block::Loop { armed, items, comp, .. }) => Ok((armed, items, comp)),
+		_ => Err(Err::new(ErrorClass::FatalError("loop instruction outside of a loop block".to_string()), Node::void()))
+	}
+}
+
+
+fn step<'a> (env: &mut Env, chunk: &Chunk, op: Op, stack: &mut Vec<Node>, blocks: &mut Vec<Block<'a>>, pc: &mut usize) -> Result<(), Err<'a>> {
+	let site = &chunk.sites[*pc - 1];
+	env.budget.tick()?;
+	match op {
+		Op::Constant(index) => { stack.push(chunk.constants[index].clone()); },
+		Op::Load(index) => {
+			let value = match env.data[env.depth].lookup(chunk.ids[index], &chunk.names[index]) {
+				Ok(var) => var,
+				Err(e) => env.import.find(chunk.ids[index]).ok_or(e)? // modules don't shadow local bindings
+			};
+			stack.push(value);
+		},
+		Op::LoadLocal(index, slot) => {
+			let value = match env.data[env.depth].lookup_slot(slot, chunk.ids[index], &chunk.names[index]) {
+				Ok(var) => var,
+				Err(e) => env.import.find(chunk.ids[index]).ok_or(e)?
+			};
+			stack.push(value);
+		},
+		Op::LoadScope => { stack.push(env.data[env.depth].as_node(Node::symbol("<ident>".to_string()))); },
+		Op::Store(index) => {
+			let value = stack.last().unwrap().clone();
+			env.data[env.depth].assign(chunk.ids[index], &chunk.names[index], value);
+		},
+		Op::StoreLocal(index, slot) => {
+			let value = stack.last().unwrap().clone();
+			env.data[env.depth].assign_slot(slot, chunk.ids[index], &chunk.names[index], value);
+		},
+		Op::Bind(index) => {
+			let value = stack.pop().unwrap();
+			env.data[env.depth].assign(chunk.ids[index], &chunk.names[index], value);
+		},
+		Op::BindLocal(index, slot) => {
+			let value = stack.pop().unwrap();
+			env.data[env.depth].assign_slot(slot, chunk.ids[index], &chunk.names[index], value);
+		},
+		Op::Pop => { stack.pop(); },
+		Op::Binary(index) => {
+			let r_op = stack.pop().unwrap();
+			let l_op = stack.pop().unwrap();
+			env.budget.allocate(1)?;
+			stack.push(binary_op(&chunk.operators[index], l_op, r_op, site)?);
+		},
+		Op::MakeList(len) => {
+			env.budget.allocate(len + 2)?;
+			let elems = stack.split_off(stack.len() - len);
+			stack.push(Node::new(NodeClass::List, site.1, vec![Node::new(NodeClass::Field, [0, 0], elems)]));
+		},
+		Op::Paren(len) => {
+			let mut elems = stack.split_off(stack.len() - len);
+			if elems.is_empty() { return Err(Err::new(ErrorClass::IndexError(0.0, "()".to_string()), site_node(site))); }
+			stack.push(elems.swap_remove(0));
+		},
+		Op::EnterScope => {
+			let scope = env.data[env.depth].clone();
+			env.data.push(scope);
+			env.depth += 1;
+		},
+		Op::ExitScope => {
+			env.data.pop();
+			env.depth -= 1;
+		},
+		Op::Jump(target) => { *pc = target; },
+		Op::JumpIfFalse(target) => {
+			if !stack.pop().unwrap().into_boolean()? { *pc = target; }
+		},
+		Op::Call(len, label) => {
+			let arguments = stack.split_off(stack.len() - len);
+			let callee = stack.pop().unwrap();
+			env.budget.allocate(len + 1)?; // function namespace
+			stack.push(call_object(env, callee, arguments, &chunk.constants[label])?);
+		},
+		Op::TailCall(len, label) => {
+			let mut call = stack.split_off(stack.len() - len);
+			call.insert(0, chunk.constants[label].clone());
+			call.insert(0, stack.pop().unwrap());
+			return Err(Err::new(ErrorClass::Signal("TailCall"), Node::new(NodeClass::Call, site.1, call)));
+		},
+		Op::Builtin(index, len) => {
+			let arguments = stack.split_off(stack.len() - len);
+			let value = search_library(env, LIBRARY[index], Node::new(NodeClass::Field, [0, 0], arguments))?;
+			env.budget.allocate(0)?; // charged for the nodes the builtin built
+			stack.push(value);
+		},
+		Op::Access(rhs, child, field) => {
+			let object = stack.pop().unwrap();
+			stack.push(object_access(env, object, &chunk.constants[rhs], &chunk.children[child], field)?);
+		},
+		Op::Member(index) => {
+			let object = stack.pop().unwrap();
+			if object.id().1 != "ObjectInstance" { return Err(Err::new(ErrorClass::UndefinedOperation(".".to_string(), object.get_type(), "Symbol"), object)); }
+			stack.push(instance_scope(env, &object)?.lookup(chunk.ids[index], &chunk.names[index])?);
+		},
+		Op::Import(index) => {
+			let statement = &chunk.constants[index];
+			let module = import_module(env, &statement.branches[0].into_string()?)?;
+			let names = &statement.branches[2];
+			if names.branches.is_empty() {
+				let alias = match statement.branches[1].get_type() {
+					"Void" => Node::symbol(module.id().0.to_string()), // the file's stem
+					_ => statement.branches[1].clone()
+				};
+				env.import.set(alias, module.clone())?;
+			}
+			else {
+				let members = instance_scope(env, &module)?;
+				for name in names.branches.iter() { env.data[env.depth].assign(name.key_id(), name, members.get(name.clone())?); }
+			}
+			stack.push(module);
+		},
+		Op::Export(index) => {
+			env.exports.write().unwrap().extend(chunk.constants[index].branches.iter().cloned());
+			stack.push(Node::void());
+		},
+		Op::SetupLoop(exit) => {
+			blocks.push(Block::Loop { depth: env.depth, height: stack.len(), trace: env.trace.len(), head: *pc, exit, armed: false, items: None, comp: Vec::new() });
+		},
+		Op::SetupIter(exit) => {
+			let iterator = stack.pop().unwrap().validate_type("List")?;
+			let items = iterator.branches[0].branches.clone();
+			blocks.push(Block::Loop { depth: env.depth, height: stack.len(), trace: env.trace.len(), head: *pc, exit, armed: false, items: Some((items, 0)), comp: Vec::new() });
+		},
+		Op::IterNext(index, exit) => {
+			let (_, items, _) = current_loop(blocks)?;
+			let elem = match items {
+				Some((elems, pos)) if *pos < elems.len() => {
+					*pos += 1;
+					elems[*pos - 1].clone()
+				},
+				_ => {
+					*pc = exit;
+					return Ok(());
+			}};
+			env.data[env.depth].assign(chunk.ids[index], &chunk.names[index], elem);
+		},
+		Op::Arm => { *current_loop(blocks)?.0 = true; },
+		Op::Collect => {
+			let value = stack.pop().unwrap();
+			let (armed, _, comp) = current_loop(blocks)?;
+			*armed = false;
+			comp.push(value);
+		},
+		Op::EndLoop => {
+			if let Some(Block::Loop { comp, .. }) = blocks.pop() {
+				env.budget.allocate(comp.len() + 2)?;
+				stack.push(Node::new(NodeClass::List, site.1, vec![Node::new(NodeClass::Field, site.1, comp)]));
+		}},
+		Op::SetupExpect(handler, kind) => {
+			blocks.push(Block::Expect { depth: env.depth, height: stack.len(), trace: env.trace.len(), handler, kind });
+		},
+		Op::SetupTry(table, kinds) => {
+			blocks.push(Block::Try { depth: env.depth, height: stack.len(), trace: env.trace.len(), table, kinds });
+		},
+		Op::SetupFinally(cleanup) => {
+			blocks.push(Block::Finally { depth: env.depth, height: stack.len(), trace: env.trace.len(), cleanup, running: false, pending: None });
+		},
+		Op::EnterFinally => {
+			if let Some(Block::Finally { running, .. }) = blocks.last_mut() { *running = true; }
+		},
+		Op::EndFinally => {
+			stack.pop();
+			if let Some(Block::Finally { pending: Some((err, frames)), .. }) = blocks.pop() {
+				env.trace.extend(frames);
+				return Err(err);
+		}},
+		Op::PopBlock => { blocks.pop(); }
+	}
+	Ok(())
+}
+
+
+pub fn binary_op<'a> (oper: &str, l_op: Node, r_op: Node, site: &Site) -> Result<Node, Err<'a>> {
+	// numbers are matched first, they're what loops spend their time on
+	if let (NodeClass::Number(num1), NodeClass::Number(num2)) = (&*l_op.signature, &*r_op.signature) {
+		let (num1, num2) = (*num1, *num2);
+		return match oper {
+			"+" => Ok(Node::number(num1+num2)),
+			"-" => Ok(Node::number(num1-num2)),
+			"*" => Ok(Node::number(num1*num2)),
+			"/" => Ok(Node::number(num1/num2)),
+			"%" => Ok(Node::number(num1%num2)),
+			"^" => Ok(Node::number(num1.powf(num2))),
+
+			">" => Ok(Node::boolean(num1>num2)),
+			"<" => Ok(Node::boolean(num1<num2)),
+			"=" => Ok(Node::boolean(num1==num2)),
+			_ => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), "Number", "Number"), site_node(site)))
+		};
+	}
+	match (l_op.get_type(), r_op.get_type()) {
+		("String", "String") => {
+			let str1 = l_op.into_string()?;
+			let str2 = r_op.into_string()?;
+			match oper {
+				"=" => Ok(Node::boolean(str1 == str2)),
+				_ => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), "String", "String"), site_node(site)))
+		}},
+		// operation between list and any other type
+		("List", _) | (_, "List") => {
+			let (list, other) = match l_op.get_type() {
+				"List" => (l_op, r_op),
+				_ => (r_op, l_op)
+			};
+
+			match oper {
+				"=" => Ok(Node::boolean(list==other)),
+				"+" => {
+					let mut list = list;
+					list.branches_mut()[0].branches_mut().push(other); // only copied if the list is still bound somewhere
+					Ok(list)
+				},
+				_ => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), "List", other.get_type()), site_node(site)))
+		}},
+		(l, r) => Err(Err::new(ErrorClass::UndefinedOperation(oper.to_string(), l, r), site_node(site)))
+	}
+}
+
+
+fn call_object<'a> (env: &mut Env, callee: Node, arguments: Vec<Node>, label: &Node) -> Result<Node, Err<'a>> {
+	if env.calls >= env.call_limit { return Err(Err::new(ErrorClass::FatalError("recursion limit".to_string()), callee)); }
+	env.calls += 1;
+	let result = call_chain(env, callee, arguments, label.clone());
+	env.calls -= 1;
+	result
+}
+
+
+// tail calls replace the callee and go around again instead of nesting another call
+fn call_chain<'a> (env: &mut Env, mut callee: Node, mut arguments: Vec<Node>, mut label: Node) -> Result<Node, Err<'a>> {
+	let trace = env.trace.len();
+	loop {
+		if callee.get_type() != "Object" { return Err(Err::new(ErrorClass::TypeMismatch("Object", callee.get_type()), callee)); }
+		let func_ns = NameSpace::frame(&callee.branches[0], arguments, env.depth)?;
+		let code = code_for(&callee)?;
+
+		match run_scoped(env, func_ns, &code) {
+			Ok(val) => {
+				if ("<ident>", "ObjectInstance") == val.id() { // set instance's name to caller's name
+					return Ok(Node::new_shared(NodeClass::ObjectInst(label.into_string()?), val.span, val.branches.clone()));
+				}
+				return Ok(val);
+			},
+			Err(e) => {
+				match *e.class {
+					ErrorClass::Signal("StopFunction") => {
+						env.trace.truncate(trace);
+						return Ok(e.cause);
+					},
+					ErrorClass::Signal("TailCall") => {
+						env.trace.truncate(trace);
+						let mut parts = e.cause.branches.iter().cloned();
+						callee = parts.next().unwrap();
+						label = parts.next().unwrap();
+						arguments = parts.collect();
+					},
+					_ => { return Err(e); }
+		}}}
+	}
+}
+
+
+fn object_access<'a> (env: &mut Env, object: Node, expr: &Node, code: &Chunk, field: bool) -> Result<Node, Err<'a>> {
+	match object.id() {
+		("_", "List") => {
+			let value = match field {
+				true => {
+					let scope = env.data[env.depth].clone();
+					run_scoped(env, scope, code)?
+				},
+				false => run(env, code)?
+			};
+			let slice = match value.branches.first() {
+				Some(slice) => slice.clone(),
+				None => { return Err(Err::new(ErrorClass::TypeMismatch("List", value.get_type()), value)); }
+			};
+			env.budget.allocate(slice.branches.len() + 1)?;
+			slice_list(object, slice)
+		},
+		(inst_of, "ObjectInstance") => {
+			let inst_of = inst_of.to_string();
+			let target_ns = instance_scope(env, &object)?;
+			// operations on object instances are allowed to access global variables
+			target_ns.extend(&env.data[env.depth]);
+
+			let trace = env.trace.len();
+			match run_scoped(env, target_ns, code) {
+				Ok(val) => {
+					if ("<ident>", "ObjectInstance") == val.id() { return Ok(Node::new_shared(NodeClass::ObjectInst(inst_of), val.span, val.branches.clone())); }
+					Ok(val)
+				},
+				Err(e) => {
+					if ErrorClass::Signal("StopFunction") == *e.class {
+						env.trace.truncate(trace);
+						return Ok(e.cause);
+					}
+					Err(e)
+			}}
+		},
+		(_, _) => Err(Err::new(ErrorClass::UndefinedOperation(":".to_string(), object.get_type(), expr.get_type()), object))
+	}
+}
+
+
+// bindings of an instance, the link to a calling function's scope refers to that scope directly
+fn instance_scope<'a> (env: &Env, object: &Node) -> Result<NameSpace, Err<'a>> {
+	match object.id().0 {
+		"<extern_link>" => {
+			match env.data.get(object.branches[0].into_number()? as usize) {
+				Some(scope) => Ok(scope.clone()),
+				None => Err(Err::new(ErrorClass::VoidReference("[@]".to_string()), object.clone()))
+		}},
+		_ => NameSpace::from(object.branches[0].clone(), object.branches[1].clone())
+	}
+}
+
+
+fn slice_list<'a> (object: Node, slice: Node) -> Result<Node, Err<'a>> {
+	let array = &object.branches[0];
+	match slice.branches.len() {
+		1 => {
+			let index = slice.branches[0].into_number()?;
+			if index < 0.0 || index as usize >= array.branches.len() { return Err(Err::new(ErrorClass::IndexError(index, object.decode()), object)); }
+			Ok(array.branches[index as usize].clone())
+		},
+		2 => {
+			let start = slice.branches[0].into_number()?;
+			let stop = slice.branches[1].into_number()?;
+
+			if start > stop { return Err(Err::new(ErrorClass::IndexError(start, object.decode()), object)); }
+			if start < 0.0 { return Err(Err::new(ErrorClass::IndexError(start, object.decode()), object)); }
+			if stop as usize > array.branches.len() { return Err(Err::new(ErrorClass::IndexError(stop, object.decode()), object)); }
+
+			let elems = array.branches[start as usize..stop as usize].to_vec();
+			Ok(Node::new(NodeClass::List, object.span, vec![Node::new(NodeClass::Field, [0, 0], elems)]))
+		},
+		_ => Err(Err::new(ErrorClass::IndexError(2.0, object.decode()), slice))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use crate::evaluator::{ Env, eval_file };
+	
+	// each step multiplies n by ten and adds its own digit, so the result spells out the order they ran in
+	fn order (source: &str) -> Option<String> {
+		eval_file(Env::create(), "<vm>", format!("n <- 0\n{}\nn\n", source), false).map(|value| value.decode())
+	}
+	
+	#[test]
+	fn finally_order () {
+		assert_eq!(order("try { n <- n * 10 + 1 raise(\"Oops\") n <- n * 10 + 9 } catch (e) { n <- n * 10 + 2 } finally { n <- n * 10 + 3 }"), Some(String::from("123")));
+		assert_eq!(order("try { n <- n * 10 + 1 } catch (e) { n <- n * 10 + 2 } finally { n <- n * 10 + 3 }"), Some(String::from("13")));
+		// a clause that doesn't list the kind lets it through, after the cleanup ran
+		assert_eq!(order("try { try { n <- n * 10 + 1 raise(\"Oops\") } catch (e, Other) { n <- n * 10 + 9 } finally { n <- n * 10 + 3 } } catch (e) { n <- n * 10 + 4 }"), Some(String::from("134")));
+	}
+	
+	#[test]
+	fn finally_after_expect () {
+		// raised inside an expect that doesn't handle it, the cleanup still runs before the outer handler
+		assert_eq!(order("try { try { x <- raise(\"Oops\"):expect(e)(\"Other\", 9) } finally { n <- n * 10 + 3 } } catch (e) { if (e.kind = \"Oops\") { n <- n * 10 + 4 } }"), Some(String::from("34")));
+		assert_eq!(order("try { x <- raise(\"Oops\"):expect(e)(\"Oops\", 5) n <- x } finally { n <- n * 10 + 3 }"), Some(String::from("53")));
+	}
+	
+	#[test]
+	fn finally_replaces_error () {
+		let kind = eval_file(Env::create(), "<vm>", String::from("try { try { raise(\"First\") } finally { raise(\"Second\") } } catch (e) { e.kind }\n"), false);
+		assert_eq!(kind.map(|value| value.decode()), Some(String::from("Second")));
+		let value = eval_file(Env::create(), "<vm>", String::from("f <- obj(a) { try { out(a) } finally { a <- 0 } }\nf(4)\n"), false);
+		assert_eq!(value.map(|value| value.decode()), Some(String::from("4")));
+	}
+}